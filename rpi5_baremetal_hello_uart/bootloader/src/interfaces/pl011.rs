@@ -1,5 +1,9 @@
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use driver::{Device, Driver, ProbeError};
+use dtb::DtbNode;
+use mutex::SpinLock;
 use tock_registers::{
     interfaces::{ReadWriteable, Readable, Writeable},
     register_bitfields, register_structs,
@@ -12,6 +16,102 @@ pub enum UartNum {
     Rp1 { device_num: u8 },
 }
 
+impl UartNum {
+    // UART0 of RP1 and the stride of UART0-5, in the address space of RP1
+    const RP1_UART0: u32 = 0x4003_0000;
+    const RP1_UART_STRIDE: u32 = 0x4000;
+    const RP1_UARTS: u32 = 6;
+
+    /// RP1 UART of the node, numbered from the untranslated 'reg' address
+    fn rp1(node: DtbNode) -> Option<Self> {
+        let address_cells = node.parent().ok()??.address_cells().ok()?;
+        let address = node
+            .property("reg")
+            .ok()??
+            .as_cells()?
+            .read(address_cells)?;
+        // the upper cells select RP1 on PCIe, e.g. 0xc0_4003_0000
+        let offset = (address as u32).checked_sub(Self::RP1_UART0)?;
+        let device_num = offset / Self::RP1_UART_STRIDE;
+        (offset % Self::RP1_UART_STRIDE == 0 && device_num < Self::RP1_UARTS).then_some(
+            UartNum::Rp1 {
+                device_num: device_num as u8,
+            },
+        )
+    }
+
+    // UART_CLK used when the device tree does not describe it
+    fn default_clock(&self) -> u32 {
+        if *self == UartNum::Debug {
+            4400_0000
+        } else {
+            4800_0000
+        }
+    }
+}
+
+pub const DRIVER: Driver = Driver {
+    name: "pl011",
+    compatibles: &["arm,pl011-axi", "arm,pl011"],
+    probe,
+};
+
+const MAX_PORTS: usize = 4;
+// the console is set up from /chosen before the drivers are probed, so the probe leaves it alone
+static CONSOLE: AtomicUsize = AtomicUsize::new(0);
+static PORTS: SpinLock<[Option<(usize, Pl011Uart)>; MAX_PORTS]> =
+    SpinLock::new([const { None }; MAX_PORTS]);
+
+pub fn set_console(address: usize) {
+    CONSOLE.store(address, Ordering::Relaxed);
+}
+
+fn probe(device: &Device) -> Result<(), ProbeError> {
+    let (address, _size) = device.region(0).ok_or(ProbeError::Device("no registers"))?;
+    let uart = Pl011Uart::new(address as *const u32);
+    if address != CONSOLE.load(Ordering::Relaxed) {
+        // "arm,pl011-axi" is a UART of RP1
+        let kind = match device.matched() {
+            0 => UartNum::rp1(device.node()).ok_or(ProbeError::Device("not an RP1 UART"))?,
+            _ => UartNum::Debug,
+        };
+        let clock = uart_clock(device.node()).unwrap_or(kind.default_clock());
+        uart.init_with_clock(clock, 115200);
+    }
+    let mut ports = PORTS.lock();
+    let port = ports
+        .iter_mut()
+        .find(|port| port.is_none())
+        .ok_or(ProbeError::Device("too many ports"))?;
+    *port = Some((address, uart));
+    Ok(())
+}
+
+/// runs `f` with the port probed at `address`
+pub fn with_port<R>(address: usize, f: impl FnOnce(&Pl011Uart) -> R) -> Option<R> {
+    PORTS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|(port_address, _)| *port_address == address)
+        .map(|(_, uart)| f(uart))
+}
+
+/// UART_CLK of a pl011 node, None when the dtb does not describe it
+///
+/// a clock which cannot be resolved is not fatal, the caller falls back to the default of UartNum
+pub fn uart_clock(node: DtbNode) -> Option<u32> {
+    let rate = match node.clock_rate("uartclk") {
+        Ok(Some(rate)) => Some(rate),
+        _ => node
+            .property("clock-frequency")
+            .ok()
+            .flatten()
+            .and_then(|property| property.as_u64()),
+    };
+    rate.and_then(|rate| u32::try_from(rate).ok())
+}
+
 // PL011 specification r1p5
 
 register_structs! {
@@ -135,19 +235,28 @@ impl Pl011Uart {
         self.registers.line_control.modify(UARTLCR::FEN::CLEAR);
     }
 
+    // baudrate of the console options such as "115200n8" in stdout-path
+    pub fn parse_baudrate(options: &str) -> Option<u32> {
+        let end = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        options[..end].parse().ok()
+    }
+
     pub fn init(&self, uart_kind: UartNum, baudrate: u32) {
+        self.init_with_clock(uart_kind.default_clock(), baudrate);
+    }
+
+    pub fn init_with_clock(&self, uart_clk: u32, baudrate: u32) {
         self.flush();
         self.disabled();
 
         // calculate clock divisor
-        let uart_clk = if uart_kind == UartNum::Debug {
-            4400_0000
-        } else {
-            4800_0000
-        };
         assert!(uart_clk > 368_6400); // UART_CLK > 3.6864MHz is required
-        let divisor_i = uart_clk / baudrate / 16; // integer part(16bit)
-        let divisor_f = ((uart_clk * 8 / baudrate + 1) / 2) & 0b11_1111; // fractional part(6 bit)
+        // uart_clk * 8 overflows u32 above 536MHz
+        let (uart_clk, baudrate) = (u64::from(uart_clk), u64::from(baudrate));
+        let divisor_i = (uart_clk / baudrate / 16) as u32; // integer part(16bit)
+        let divisor_f = (((uart_clk * 8 / baudrate + 1) / 2) & 0b11_1111) as u32; // fractional part(6 bit)
         self.registers.integer_baud_rate.set(divisor_i);
         self.registers.fractional_baud_rate.set(divisor_f);
         // enable fifo
@@ -1,2 +1 @@
 pub mod rp1_gpio;
-pub mod rp1_info;
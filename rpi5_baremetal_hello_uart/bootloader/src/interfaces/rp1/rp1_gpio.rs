@@ -1,10 +1,191 @@
+use tock_registers::fields::FieldValue;
 use tock_registers::interfaces::ReadWriteable;
 use tock_registers::register_bitfields;
 use tock_registers::register_structs;
 use tock_registers::registers::{ReadOnly, ReadWrite};
 
-use crate::interfaces::rp1::rp1_info::Rp1Block;
-use crate::interfaces::rp1::rp1_info::get_block_address;
+use driver::{Device, Driver, ProbeError};
+use dtb::{Bias, PinConfig};
+use mutex::{OnceLock, SpinLock};
+
+pub const DRIVER: Driver = Driver {
+    name: "rp1-gpio",
+    compatibles: &["raspberrypi,rp1-gpio"],
+    probe,
+};
+
+static GPIO: OnceLock<SpinLock<Rp1GPIO>> = OnceLock::new();
+
+fn probe(device: &Device) -> Result<(), ProbeError> {
+    // io_bank0, sys_rio0 and pads_bank0 in the order of the binding
+    let (Some((io_bank0, _)), Some((pads_bank0, _))) = (device.region(0), device.region(2)) else {
+        return Err(ProbeError::Device("io_bank0 and pads_bank0 are required"));
+    };
+    GPIO.set(SpinLock::new(Rp1GPIO::new(io_bank0, pads_bank0)))
+        .map_err(|_| ProbeError::Device("rp1 gpio is already probed"))
+}
+
+/// runs `f` with the probed GPIO block, None before it is probed
+pub fn with_gpio<R>(f: impl FnOnce(&Rp1GPIO) -> R) -> Option<R> {
+    GPIO.get().map(|gpio| f(&gpio.lock()))
+}
+
+/// applies a configuration of the pinctrl-rp1 bindings, passed to `Registry::with_pinctrl`
+///
+/// the pins of other controllers are left as the firmware set them
+pub fn apply_pin_config(config: &PinConfig) -> Result<(), ProbeError> {
+    if config
+        .controller()
+        .match_compatible(DRIVER.compatibles)?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let gpio = GPIO
+        .get()
+        .ok_or(ProbeError::Device("rp1 gpio is not probed"))?
+        .lock();
+    let function = config.function()?;
+    let bias = config.bias()?;
+    let drive = config
+        .drive_strength()?
+        .map(|ma| match ma {
+            2 => Ok(GPIO::DRIVE::STRENGTH_2mA),
+            4 => Ok(GPIO::DRIVE::STRENGTH_4mA),
+            8 => Ok(GPIO::DRIVE::STRENGTH_8mA),
+            12 => Ok(GPIO::DRIVE::STRENGTH_12mA),
+            // not rounded, a strength the pads cannot drive is an error in the device tree
+            _ => Err(ProbeError::Device("unsupported drive-strength")),
+        })
+        .transpose()?;
+    for pin in config.pins()? {
+        let pin = pin?
+            .strip_prefix("gpio")
+            .and_then(|num| num.parse::<usize>().ok())
+            .filter(|num| *num < FUNCTIONS.len())
+            .ok_or(ProbeError::Device("unknown pin"))?;
+        if let Some(function) = function {
+            let funcsel = FUNCTIONS[pin]
+                .iter()
+                .position(|name| *name == function)
+                .ok_or(ProbeError::Device("function is not available on the pin"))?;
+            gpio.set_func(pin, funcsel as u32);
+            gpio.peripheral_output(pin);
+        }
+        if let Some(bias) = bias {
+            gpio.set_bias(pin, bias);
+        }
+        if let Some(drive) = drive {
+            gpio.set_drive(pin, drive);
+        }
+    }
+    Ok(())
+}
+
+// FUNCSEL of each function of GPIO0-27 (a0 to a8 of the RP1 datasheet), as named by pinctrl-rp1
+// in Linux. "gpio" is the sys_rio function which the GPIO registers drive
+const FUNCTIONS: [[&str; 9]; 28] = [
+    [
+        "spi0", "dpi", "uart1", "i2c0", "", "gpio", "proc_rio", "pio", "spi2",
+    ],
+    [
+        "spi0", "dpi", "uart1", "i2c0", "", "gpio", "proc_rio", "pio", "spi2",
+    ],
+    [
+        "spi0", "dpi", "uart1", "i2c1", "ir", "gpio", "proc_rio", "pio", "spi2",
+    ],
+    [
+        "spi0", "dpi", "uart1", "i2c1", "ir", "gpio", "proc_rio", "pio", "spi2",
+    ],
+    [
+        "gpclk0", "dpi", "uart2", "i2c2", "ri0", "gpio", "proc_rio", "pio", "spi3",
+    ],
+    [
+        "gpclk1", "dpi", "uart2", "i2c2", "dtr0", "gpio", "proc_rio", "pio", "spi3",
+    ],
+    [
+        "gpclk2", "dpi", "uart2", "i2c3", "dcd0", "gpio", "proc_rio", "pio", "spi3",
+    ],
+    [
+        "spi0", "dpi", "uart2", "i2c3", "dsr0", "gpio", "proc_rio", "pio", "spi3",
+    ],
+    [
+        "spi0", "dpi", "uart3", "i2c0", "", "gpio", "proc_rio", "pio", "spi4",
+    ],
+    [
+        "spi0", "dpi", "uart3", "i2c0", "", "gpio", "proc_rio", "pio", "spi4",
+    ],
+    [
+        "spi0", "dpi", "uart3", "i2c1", "", "gpio", "proc_rio", "pio", "spi4",
+    ],
+    [
+        "spi0", "dpi", "uart3", "i2c1", "", "gpio", "proc_rio", "pio", "spi4",
+    ],
+    [
+        "pwm0", "dpi", "uart4", "i2c2", "aaud", "gpio", "proc_rio", "pio", "spi5",
+    ],
+    [
+        "pwm0", "dpi", "uart4", "i2c2", "aaud", "gpio", "proc_rio", "pio", "spi5",
+    ],
+    [
+        "pwm0", "dpi", "uart4", "i2c3", "uart0", "gpio", "proc_rio", "pio", "spi5",
+    ],
+    [
+        "pwm0", "dpi", "uart4", "i2c3", "uart0", "gpio", "proc_rio", "pio", "spi5",
+    ],
+    [
+        "spi1",
+        "dpi",
+        "dsi0_te_ext",
+        "",
+        "uart0",
+        "gpio",
+        "proc_rio",
+        "pio",
+        "",
+    ],
+    [
+        "spi1",
+        "dpi",
+        "dsi1_te_ext",
+        "",
+        "uart0",
+        "gpio",
+        "proc_rio",
+        "pio",
+        "",
+    ],
+    [
+        "spi1", "dpi", "i2s0", "pwm0", "i2s1", "gpio", "proc_rio", "pio", "gpclk1",
+    ],
+    [
+        "spi1", "dpi", "i2s0", "pwm0", "i2s1", "gpio", "proc_rio", "pio", "",
+    ],
+    [
+        "spi1", "dpi", "i2s0", "gpclk0", "i2s1", "gpio", "proc_rio", "pio", "",
+    ],
+    [
+        "spi1", "dpi", "i2s0", "gpclk1", "i2s1", "gpio", "proc_rio", "pio", "",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "i2c3", "i2s1", "gpio", "proc_rio", "pio", "",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "i2c3", "i2s1", "gpio", "proc_rio", "pio", "",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "", "i2s1", "gpio", "proc_rio", "pio", "spi2",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "mic", "i2s1", "gpio", "proc_rio", "pio", "spi3",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "mic", "i2s1", "gpio", "proc_rio", "pio", "spi5",
+    ],
+    [
+        "sdio0", "dpi", "i2s0", "mic", "i2s1", "gpio", "proc_rio", "pio", "spi1",
+    ],
+];
 
 register_structs! {
     /// GPIO Peripheral Register Block
@@ -190,7 +371,7 @@ register_bitfields!(
             STRENGTH_2mA = 0b00,
             STRENGTH_4mA = 0b01,
             STRENGTH_8mA = 0b10,
-            STRENGTH_16mA = 0b11,
+            STRENGTH_12mA = 0b11,
         ], // drive strength (default 0b01)
         IE OFFSET(6) NUMBITS(1) [], // input enable
         // output disable (has priority over output enable from peripheral)
@@ -204,14 +385,10 @@ pub struct Rp1GPIO {
 }
 
 impl Rp1GPIO {
-    pub fn new() -> Self {
+    pub fn new(io_bank0: usize, pads_bank0: usize) -> Self {
         Self {
-            io_bank0: unsafe {
-                &mut *(get_block_address(Rp1Block::PadsBank0).address as *mut IoBank0)
-            },
-            pads_bank0: unsafe {
-                &mut *(get_block_address(Rp1Block::PadsBank0).address as *mut PadsBank0)
-            },
+            io_bank0: unsafe { &mut *(io_bank0 as *mut IoBank0) },
+            pads_bank0: unsafe { &mut *(pads_bank0 as *mut PadsBank0) },
         }
     }
 
@@ -227,6 +404,30 @@ impl Rp1GPIO {
         pads.modify(GPIO::OD::CLEAR + GPIO::PDE::CLEAR + GPIO::PUE::CLEAR);
     }
 
+    /// lets the function selected by FUNCSEL drive the output enable, like pinctrl-rp1 does
+    ///
+    /// an input function such as UART RX leaves the output disabled, and the pulls are kept
+    pub fn peripheral_output(&self, gpio_num: usize) {
+        let io = self.io_bank0.gpio.get(gpio_num).unwrap();
+        io.1.modify(GPIO_CTRL::OUTOVER::FromPeripheral + GPIO_CTRL::OEOVER::FromPeripheral);
+        let pads = self.pads_bank0.gpio.get(gpio_num).unwrap();
+        pads.modify(GPIO::OD::CLEAR + GPIO::IE::SET);
+    }
+
+    pub fn set_bias(&self, gpio_num: usize, bias: Bias) {
+        let pads = self.pads_bank0.gpio.get(gpio_num).unwrap();
+        pads.modify(match bias {
+            Bias::Disable => GPIO::PDE::CLEAR + GPIO::PUE::CLEAR,
+            Bias::PullUp => GPIO::PDE::CLEAR + GPIO::PUE::SET,
+            Bias::PullDown => GPIO::PDE::SET + GPIO::PUE::CLEAR,
+        });
+    }
+
+    pub fn set_drive(&self, gpio_num: usize, drive: FieldValue<u32, GPIO::Register>) {
+        let pads = self.pads_bank0.gpio.get(gpio_num).unwrap();
+        pads.modify(drive);
+    }
+
     pub fn gpio_enable(&self, gpio_num: usize) {
         let gpio = self.io_bank0.gpio.get(gpio_num).unwrap();
         gpio.1.modify(GPIO_CTRL::OEOVER::Enable);
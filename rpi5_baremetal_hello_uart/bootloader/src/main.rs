@@ -1,4 +1,3 @@
-#![feature(once_cell_get_mut)]
 #![no_std]
 #![no_main]
 #![recursion_limit = "256"]
@@ -8,31 +7,31 @@ pub mod print;
 pub mod interfaces;
 mod systimer;
 use crate::interfaces::{
-    pl011::{Pl011Uart, UartNum},
-    rp1::{rp1_gpio::Rp1GPIO, rp1_info::get_block_address},
+    pl011::{self, Pl011Uart, UartNum},
+    rp1::rp1_gpio,
 };
 use core::{
     arch::{asm, global_asm},
-    cell::OnceCell,
     ops::ControlFlow,
     panic::PanicInfo,
 };
-use dtb::{self, DtbParser};
+use driver::{Driver, Registry};
+use dtb::{self, DtbError, DtbErrorKind, DtbParser};
+use mutex::OnceLock;
 use systimer::SystemTimer;
 
 unsafe extern "C" {
+    static mut _IMAGE_START: usize;
     static mut _BSS_START: usize;
     static mut _BSS_END: usize;
     static mut _STACK_TOP: usize;
 }
 
 const PL011_UART_ADDR: *const u32 = 0x10_7D00_1000 as *const u32;
-const RP1_OFFSET_ADDR: usize = 0x1f_0000_0000;
-const RP1_BASE_ADDR: u32 = 0x4000_0000;
-const RP1_UART0_ADDR: u32 =
-    get_block_address(crate::interfaces::rp1::rp1_info::Rp1Block::Uart0).address;
-const UART0_ADDR: *const u32 =
-    (RP1_OFFSET_ADDR + (RP1_UART0_ADDR - RP1_BASE_ADDR) as usize) as *const u32;
+// where the firmware places the dtb (device_tree_address in config.txt)
+const DTB_ADDR: usize = 0x2000_0000;
+// probed in this order, GPIO comes first for the pins of the UARTs
+static DRIVERS: [Driver; 2] = [rp1_gpio::DRIVER, pl011::DRIVER];
 
 // 最初に実行される部分 _startが最初に呼び出され、スタックの設定を行ったらresetに飛ぶ
 global_asm!(
@@ -61,15 +60,39 @@ loop:
 
 #[unsafe(no_mangle)]
 extern "C" fn main() -> ! {
-    let dtb = DtbParser::init(0x2000_0000).unwrap();
-    let pl011_debug_uart_addr = OnceCell::new();
-    dtb.find_node(None, Some("arm,pl011"), &mut |(address, _size)| {
-        let _ = pl011_debug_uart_addr.set(address);
-        ControlFlow::Continue(())
-    })
-    .unwrap();
-    let debug_uart = Pl011Uart::new(*pl011_debug_uart_addr.get().unwrap() as *const u32);
-    debug_uart.init(UartNum::Debug, 115200);
+    // the firmware has loaded the dtb there and nothing writes to it
+    let dtb = match unsafe { DtbParser::init(DTB_ADDR) } {
+        Ok(dtb) => dtb,
+        Err(error) => dtb_error(error),
+    };
+    let pl011_debug_uart = OnceLock::new();
+    // use the console in /chosen/stdout-path like Linux does, and fall back to the first enabled pl011
+    let set_debug_uart = |node: dtb::DtbNode, options: Option<&str>| {
+        let (address, _size) = node.reg().unwrap().next().unwrap().unwrap();
+        let clock = pl011::uart_clock(node);
+        let baudrate = options
+            .and_then(Pl011Uart::parse_baudrate)
+            .unwrap_or(115200);
+        let _ = pl011_debug_uart.set((address, clock, baudrate));
+    };
+    match dtb.find_stdout_node().unwrap() {
+        Some((node, options)) if node.is_compatible("arm,pl011").unwrap() => {
+            set_debug_uart(node, options)
+        }
+        _ => dtb
+            .find_compatible_nodes(&["arm,pl011"], &mut |node, _| {
+                set_debug_uart(node, None);
+                ControlFlow::Break(())
+            })
+            .unwrap(),
+    }
+    let (debug_uart_addr, debug_uart_clock, baudrate) = *pl011_debug_uart.get().unwrap();
+    let debug_uart = Pl011Uart::new(debug_uart_addr as *const u32);
+    match debug_uart_clock {
+        Some(clock) => debug_uart.init_with_clock(clock, baudrate),
+        None => debug_uart.init(UartNum::Debug, baudrate),
+    }
+    pl011::set_console(debug_uart_addr);
     debug_uart.write("debug uart starting...\r\n");
     // check if the PL011_OFFSET_ADDR is correct
     let chip_id = unsafe { *PL011_UART_ADDR };
@@ -78,41 +101,84 @@ extern "C" fn main() -> ! {
     } else {
         debug_uart.write("PL011_OFFSET_ADDR is incorrect\r\n");
     }
-    //DEBUG_UART.call_once(|| Mutex::new(debug_uart));
+    // println goes to the console from here
+    if print::set_debug_uart(debug_uart).is_err() {
+        println!("failed to set debug uart");
+    }
+    // the stack is placed right after the image, so the image and the stack are excluded together
+    let image_start = &raw const _IMAGE_START as usize;
+    let image_end = &raw const _STACK_TOP as usize;
+    let memory_map = dtb
+        .usable_memory::<16>(&[(image_start, image_end - image_start)])
+        .unwrap();
+    for (address, size) in memory_map.regions() {
+        println!("usable memory: {:#x}..{:#x}", address, address + size);
+    }
+    let header = dtb.header();
+    println!(
+        "dtb version {}, booted on cpu {:#x}",
+        header.version, header.boot_cpuid_phys
+    );
+    for cpu in dtb.cpus().unwrap() {
+        let cpu = cpu.unwrap();
+        println!("cpu {:#x}: {:?}", cpu.mpidr(), cpu.enable_method());
+    }
     //println!("{chip_id}");
-    // if DEBUG_UART.set(debug_uart).is_err() {
-    //     println!("failed to set debug uart");
-    // }
     //println!("HelloWorld!\r\nPL011\r\n");
-    // enable GPIO14, 15
-    let gpio = Rp1GPIO::new();
-    gpio.set_func(14, 4);
-    gpio.set_func(15, 4);
-    gpio.enable_output(14);
-    gpio.enable_output(15);
-    gpio.enable_output(18);
-    // setup rp1 uart
-    let rp1_uart = Pl011Uart::new(UART0_ADDR);
-    rp1_uart.init(UartNum::Rp1 { device_num: 0 }, 115200);
-    rp1_uart.write("rp1 uart starting...\r\n");
-    // if RP1_UART.set(rp1_uart).is_err() {
-    //     println!("failed to set rp1 uart");
-    // }
+    Registry::new(&DRIVERS)
+        .with_pinctrl(rp1_gpio::apply_pin_config)
+        .probe_all(&dtb, &mut |driver, node, result| {
+            let name = node.name().unwrap_or("?");
+            match result {
+                Ok(()) => println!("{}: bound {}", driver.name, name),
+                Err(error) => println!("{}: failed to probe {}: {}", driver.name, name, error),
+            }
+        })
+        .unwrap();
+    // the pins of the rp1 uart are muxed by its pinctrl state, GPIO18 is driven by hand
+    rp1_gpio::with_gpio(|gpio| gpio.enable_output(18)).expect("rp1 gpio is not probed");
+    // the rp1 uart has been set up by its driver
+    let (rp1_uart_addr, _size) = dtb
+        .find_node_by_alias("uart0")
+        .unwrap()
+        .expect("uart0 is not in /aliases")
+        .reg()
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    let rp1_write = |s: &str| pl011::with_port(rp1_uart_addr, |uart| uart.write(s));
+    rp1_write("rp1 uart starting...\r\n");
     // init timer
-    let mut timer = SystemTimer::new();
+    let timer = SystemTimer::new();
     timer.init();
     loop {
-        gpio.gpio_enable(18);
+        rp1_gpio::with_gpio(|gpio| gpio.gpio_enable(18));
         //println!("HelloWorld!\r\n");
-        rp1_uart.write("Hello from RP1\r\n");
+        rp1_write("Hello from RP1\r\n");
         timer.wait(core::time::Duration::from_secs(1));
-        gpio.gpio_disable(18);
+        rp1_gpio::with_gpio(|gpio| gpio.gpio_disable(18));
         //println!("HelloWorld!\r\n");
-        rp1_uart.write("Hello from RP1\r\n");
+        rp1_write("Hello from RP1\r\n");
         timer.wait(core::time::Duration::from_secs(1));
     }
 }
 
+// the console is not known without the dtb, so report on the uart the firmware has set up
+fn dtb_error(error: DtbError) -> ! {
+    let debug_uart = Pl011Uart::new(PL011_UART_ADDR);
+    debug_uart.init(UartNum::Debug, 115200);
+    match error.kind() {
+        DtbErrorKind::InvalidMagic => {
+            println!("no dtb found at {:#x}, check device_tree_address", DTB_ADDR)
+        }
+        _ => println!("failed to read the dtb: {}", error),
+    }
+    loop {
+        unsafe { asm!("wfe") };
+    }
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     let debug_uart = Pl011Uart::new(PL011_UART_ADDR);
@@ -2,10 +2,11 @@ use core::fmt::{self, Write};
 
 use crate::PL011_UART_ADDR;
 use crate::interfaces::pl011::Pl011Uart;
-use mutex::SpinLock;
+use mutex::{IrqSpinLock, OnceLock};
 
-static DEBUG_UART: SpinLock<Option<Pl011Uart>> = SpinLock::new(None);
-static RP1_UART0: SpinLock<Option<Pl011Uart>> = SpinLock::new(None);
+// also printed from interrupt handlers, so interrupts are masked while a line is written
+static DEBUG_UART: OnceLock<IrqSpinLock<Pl011Uart>> = OnceLock::new();
+static RP1_UART0: OnceLock<IrqSpinLock<Pl011Uart>> = OnceLock::new();
 
 #[macro_export]
 macro_rules! print {
@@ -20,13 +21,23 @@ macro_rules! println {
     });
 }
 
+/// prints to `uart` instead of the UART which the firmware has set up
+///
+/// gives `uart` back when something has already been printed
+pub fn set_debug_uart(uart: Pl011Uart) -> Result<(), Pl011Uart> {
+    DEBUG_UART
+        .set(IrqSpinLock::new(uart))
+        .map_err(|lock| lock.into_inner())
+}
+
 pub fn _print(args: fmt::Arguments) {
-    let mut debug_uart_cell = DEBUG_UART.lock();
-    let uart = debug_uart_cell.get_or_insert_with(|| Pl011Uart::new(PL011_UART_ADDR));
-    uart.write_fmt(args).unwrap();
+    DEBUG_UART
+        .get_or_init(|| IrqSpinLock::new(Pl011Uart::new(PL011_UART_ADDR)))
+        .lock()
+        .write_fmt(args)
+        .unwrap();
 
-    let mut rp1_uart0_cell = RP1_UART0.lock();
-    if let Some(uart) = rp1_uart0_cell.as_mut() {
-        uart.write_fmt(args).unwrap();
+    if let Some(uart) = RP1_UART0.get() {
+        uart.lock().write_fmt(args).unwrap();
     }
 }
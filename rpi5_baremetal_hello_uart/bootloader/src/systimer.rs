@@ -5,30 +5,25 @@ use core::{
     arch::asm,
     num::{NonZero, NonZeroU64},
 };
+use mutex::Lazy;
 
-pub struct SystemTimer {
-    counter_frequency: Option<NonZeroU64>,
-}
+// CNTFRQ_EL0 is set by the firmware and does not change, so it is read once for every core
+static COUNTER_FREQUENCY: Lazy<NonZeroU64> =
+    Lazy::new(|| NonZero::new(SystemTimer::get_timer_frequency()).unwrap());
+
+pub struct SystemTimer {}
 
 impl SystemTimer {
     pub fn new() -> Self {
-        Self {
-            counter_frequency: None,
-        }
+        Self {}
     }
-    pub fn init(&mut self) {
-        self.counter_frequency = Some(NonZero::new(Self::get_timer_frequency()).unwrap());
+    pub fn init(&self) {
+        Lazy::force(&COUNTER_FREQUENCY);
     }
     pub fn wait(&self, duration: core::time::Duration) {
         let micros = duration.as_micros();
         let start = Self::get_timer_counter();
-        let wait_time = u128::from(
-            self.counter_frequency
-                .expect("before calling wait function call init")
-                .get()
-                / 1000
-                / 1000,
-        ) * micros;
+        let wait_time = u128::from(COUNTER_FREQUENCY.get() / 1000 / 1000) * micros;
         while u128::from(Self::get_timer_counter() - start) < wait_time {
             core::hint::spin_loop();
         }
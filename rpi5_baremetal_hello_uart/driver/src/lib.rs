@@ -0,0 +1,419 @@
+#![cfg_attr(not(test), no_std)]
+
+// driver model: binds drivers to the enabled nodes of the device tree
+//
+// a driver declares its compatibles and a probe function like a platform driver with an
+// of_match_table in Linux. the probe function receives the node with its MMIO regions and
+// interrupts already resolved, and with the pins of its "default" pinctrl state configured
+
+use core::fmt;
+use core::ops::ControlFlow;
+
+use dtb::{DtbError, DtbNode, DtbParser, Interrupt, PinConfig};
+
+/// a driver bound to the nodes which list one of `compatibles`
+pub struct Driver {
+    pub name: &'static str,
+    /// ordered by preference, see `Device::matched`
+    pub compatibles: &'static [&'static str],
+    pub probe: fn(&Device) -> Result<(), ProbeError>,
+}
+
+/// why a device could not be bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeError {
+    /// the node does not describe what the driver needs
+    Dtb(DtbError),
+    /// the driver rejected the device, with the reason for the log
+    Device(&'static str),
+}
+
+impl From<DtbError> for ProbeError {
+    fn from(error: DtbError) -> Self {
+        Self::Dtb(error)
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dtb(error) => write!(f, "{}", error),
+            Self::Device(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// a node matched by a driver, passed to its probe function
+pub struct Device<'a> {
+    node: DtbNode<'a>,
+    matched: usize,
+    regions: [Option<(usize, usize)>; Device::MAX_REGIONS],
+    interrupts: [Option<Interrupt<'a>>; Device::MAX_INTERRUPTS],
+    // every entry of the node, including the ones which were not kept
+    region_count: usize,
+    interrupt_count: usize,
+}
+
+impl<'a> Device<'a> {
+    // the RP1 GPIO block has 3 regions, the GIC 4. the entries after these are not kept, e.g.
+    // of a PCIe host bridge, and the driver reads them from the node if it needs them
+    pub const MAX_REGIONS: usize = 4;
+    pub const MAX_INTERRUPTS: usize = 4;
+
+    fn new(node: DtbNode<'a>, matched: usize) -> Result<Self, DtbError> {
+        let mut device = Self {
+            node,
+            matched,
+            regions: [None; Self::MAX_REGIONS],
+            interrupts: [None; Self::MAX_INTERRUPTS],
+            region_count: 0,
+            interrupt_count: 0,
+        };
+        // a node without 'reg' (e.g. a fixed clock) has no region
+        for region in node.reg()? {
+            let region = region?;
+            if let Some(slot) = device.regions.get_mut(device.region_count) {
+                *slot = Some(region);
+            }
+            device.region_count += 1;
+        }
+        for interrupt in node.interrupts()? {
+            let interrupt = interrupt?;
+            if let Some(slot) = device.interrupts.get_mut(device.interrupt_count) {
+                *slot = Some(interrupt);
+            }
+            device.interrupt_count += 1;
+        }
+        Ok(device)
+    }
+
+    pub fn node(&self) -> DtbNode<'a> {
+        self.node
+    }
+
+    /// index of the entry of `Driver::compatibles` which matched the node
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    /// (address, size) of a 'reg' entry translated into the CPU physical address space
+    ///
+    /// only the first MAX_REGIONS entries are kept
+    pub fn region(&self, index: usize) -> Option<(usize, usize)> {
+        self.regions.get(index).copied().flatten()
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.regions.iter().map_while(|region| *region)
+    }
+
+    /// number of 'reg' entries of the node, which can be more than the kept regions
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    /// an interrupt resolved to its controller
+    ///
+    /// only the first MAX_INTERRUPTS interrupts are kept
+    pub fn interrupt(&self, index: usize) -> Option<Interrupt<'a>> {
+        self.interrupts.get(index).copied().flatten()
+    }
+
+    pub fn interrupts(&self) -> impl Iterator<Item = Interrupt<'a>> + '_ {
+        self.interrupts.iter().map_while(|interrupt| *interrupt)
+    }
+
+    /// number of interrupts of the node, which can be more than the kept interrupts
+    pub fn interrupt_count(&self) -> usize {
+        self.interrupt_count
+    }
+}
+
+/// applies a pin configuration, see `Registry::with_pinctrl`
+pub type PinctrlFn = fn(&PinConfig) -> Result<(), ProbeError>;
+
+/// the table of the drivers of the system
+pub struct Registry<'d> {
+    drivers: &'d [Driver],
+    pinctrl: Option<PinctrlFn>,
+}
+
+impl<'d> Registry<'d> {
+    /// devices are probed in the order of `drivers`, so a driver which others depend on
+    /// (e.g. GPIO for the pins of a UART) comes first
+    pub const fn new(drivers: &'d [Driver]) -> Self {
+        Self {
+            drivers,
+            pinctrl: None,
+        }
+    }
+
+    /// configures the "default" pinctrl state of each device with `apply` before it is probed
+    ///
+    /// like the driver core of Linux, a device whose pins cannot be configured is not probed.
+    /// `apply` receives the configurations of every pin controller and decides which it handles
+    pub const fn with_pinctrl(self, apply: PinctrlFn) -> Self {
+        Self {
+            pinctrl: Some(apply),
+            ..self
+        }
+    }
+
+    /// the driver which binds `node` and the index of the matched compatible
+    ///
+    /// a node listed by several drivers is bound to the first one in the table
+    pub fn driver_for(&self, node: DtbNode) -> Result<Option<(&'d Driver, usize)>, DtbError> {
+        for driver in self.drivers {
+            if let Some(matched) = node.match_compatible(driver.compatibles)? {
+                return Ok(Some((driver, matched)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// probes every enabled node bound to a driver
+    ///
+    /// `report` receives the result of each device and a failed probe does not stop the others.
+    /// only an error of the tree itself is returned
+    pub fn probe_all<'a, F>(
+        &self,
+        parser: &'a DtbParser<'a>,
+        report: &mut F,
+    ) -> Result<(), DtbError>
+    where
+        F: FnMut(&Driver, DtbNode<'a>, Result<(), ProbeError>),
+    {
+        for driver in self.drivers {
+            let mut result = Ok(());
+            parser.find_compatible_nodes(driver.compatibles, &mut |node, matched| {
+                match self.driver_for(node) {
+                    Ok(Some((bound, _))) if core::ptr::eq(bound, driver) => {
+                        let probed = Device::new(node, matched)
+                            .map_err(ProbeError::from)
+                            .and_then(|device| {
+                                self.select_default_pins(node)?;
+                                (driver.probe)(&device)
+                            });
+                        report(driver, node, probed);
+                        ControlFlow::Continue(())
+                    }
+                    // taken by an earlier driver
+                    Ok(_) => ControlFlow::Continue(()),
+                    Err(e) => {
+                        result = Err(e);
+                        ControlFlow::Break(())
+                    }
+                }
+            })?;
+            result?;
+        }
+        Ok(())
+    }
+
+    fn select_default_pins(&self, node: DtbNode) -> Result<(), ProbeError> {
+        let (Some(apply), Some(configs)) = (self.pinctrl, node.pinctrl("default")?) else {
+            return Ok(());
+        };
+        for config in configs {
+            apply(&config?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtb::FdtWriter;
+    use std::sync::Mutex;
+
+    // (driver, node, matched, first region, first interrupt) of every fake probe
+    type Probed = (
+        &'static str,
+        String,
+        usize,
+        Option<(usize, usize)>,
+        Vec<u32>,
+    );
+    static PROBED: Mutex<Vec<Probed>> = Mutex::new(Vec::new());
+
+    fn record(driver: &'static str, device: &Device) {
+        PROBED.lock().unwrap().push((
+            driver,
+            device.node().name().unwrap().to_string(),
+            device.matched(),
+            device.region(0),
+            device
+                .interrupt(0)
+                .map_or_else(Vec::new, |irq| irq.specifier().to_vec()),
+        ));
+    }
+
+    const DRIVERS: [Driver; 4] = [
+        Driver {
+            name: "gpio",
+            compatibles: &["raspberrypi,rp1-gpio"],
+            probe: |device| {
+                assert_eq!(device.regions().count(), 3);
+                record("gpio", device);
+                Ok(())
+            },
+        },
+        Driver {
+            name: "uart",
+            compatibles: &["arm,pl011-axi", "arm,pl011"],
+            probe: |device| {
+                record("uart", device);
+                Ok(())
+            },
+        },
+        // every node is already taken by "uart"
+        Driver {
+            name: "shadowed",
+            compatibles: &["arm,primecell"],
+            probe: |device| {
+                record("shadowed", device);
+                Ok(())
+            },
+        },
+        Driver {
+            name: "gic",
+            compatibles: &["arm,gic-400"],
+            probe: |_| Err(ProbeError::Device("no distributor")),
+        },
+    ];
+
+    #[test]
+    fn probe_rpi5_devices() {
+        let test_data = std::fs::read("../dtb/test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = DtbParser::from_bytes(&test_data).unwrap();
+        let registry = Registry::new(&DRIVERS);
+
+        let mut reports = Vec::new();
+        registry
+            .probe_all(&parser, &mut |driver, node, result| {
+                reports.push((driver.name, node.name().unwrap(), result))
+            })
+            .unwrap();
+        assert_eq!(
+            *PROBED.lock().unwrap(),
+            [
+                (
+                    "gpio",
+                    String::from("gpio@c0400d0000"),
+                    0,
+                    Some((0x1f_000d_0000, 0xc000)),
+                    vec![0, 4]
+                ),
+                (
+                    "uart",
+                    String::from("serial@7d001000"),
+                    1,
+                    Some((0x10_7d00_1000, 0x200)),
+                    vec![0, 121, 4]
+                ),
+                // serial@c040034000 is disabled
+                (
+                    "uart",
+                    String::from("serial@c040030000"),
+                    0,
+                    Some((0x1f_0003_0000, 0x100)),
+                    vec![25, 4]
+                ),
+            ]
+        );
+        assert_eq!(reports.len(), 4);
+        assert_eq!(
+            reports.last(),
+            Some(&(
+                "gic",
+                "interrupt-controller@7fff9000",
+                Err(ProbeError::Device("no distributor"))
+            ))
+        );
+
+        let uart = parser.find_node_by_alias("uart0").unwrap().unwrap();
+        let (driver, matched) = registry.driver_for(uart).unwrap().unwrap();
+        assert_eq!((driver.name, matched), ("uart", 0));
+        assert!(
+            registry
+                .driver_for(parser.root().unwrap())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    // (pin, function) of every applied configuration
+    static PINS: Mutex<Vec<(String, Option<String>)>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn configure_pins_before_probe() {
+        let test_data = std::fs::read("../dtb/test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = DtbParser::from_bytes(&test_data).unwrap();
+        const UART: [Driver; 1] = [Driver {
+            name: "uart",
+            compatibles: &["arm,pl011-axi"],
+            probe: |_| Ok(()),
+        }];
+
+        let registry = Registry::new(&UART).with_pinctrl(|config| {
+            for pin in config.pins()? {
+                PINS.lock()
+                    .unwrap()
+                    .push((pin?.to_string(), config.function()?.map(str::to_string)));
+            }
+            Ok(())
+        });
+        let mut probed = 0;
+        registry
+            .probe_all(&parser, &mut |_, _, result| {
+                assert_eq!(result, Ok(()));
+                probed += 1;
+            })
+            .unwrap();
+        assert_eq!(probed, 1);
+        // serial@c040034000 is disabled, so gpio0 and gpio1 are left alone
+        assert_eq!(
+            *PINS.lock().unwrap(),
+            [
+                (String::from("gpio14"), Some(String::from("uart0"))),
+                (String::from("gpio15"), Some(String::from("uart0"))),
+            ]
+        );
+
+        // the device is not probed when its pins cannot be configured
+        let registry = Registry::new(&UART).with_pinctrl(|_| Err(ProbeError::Device("busy")));
+        registry
+            .probe_all(&parser, &mut |_, _, result| {
+                assert_eq!(result, Err(ProbeError::Device("busy")))
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn keep_the_first_regions() {
+        let mut buf = [0u8; 512];
+        let mut writer = FdtWriter::new(&mut buf).unwrap();
+        writer.begin_node("").unwrap();
+        writer.property_u32("#address-cells", 1).unwrap();
+        writer.property_u32("#size-cells", 1).unwrap();
+        writer.begin_node("pcie@1000").unwrap();
+        let reg: Vec<u8> = (1..=6u32)
+            .flat_map(|i| [i * 0x1000, 0x100])
+            .flat_map(u32::to_be_bytes)
+            .collect();
+        writer.property("reg", &reg).unwrap();
+        writer.end_node().unwrap();
+        writer.end_node().unwrap();
+        let blob = writer.finish(0).unwrap();
+        let parser = DtbParser::from_bytes(blob).unwrap();
+
+        let node = parser.find_node_by_path("/pcie@1000").unwrap().unwrap();
+        let device = Device::new(node, 0).unwrap();
+        assert_eq!(device.region_count(), 6);
+        assert_eq!(device.regions().count(), Device::MAX_REGIONS);
+        assert_eq!(device.region(3), Some((0x4000, 0x100)));
+        assert_eq!(device.region(4), None);
+        assert_eq!(device.interrupt_count(), 0);
+    }
+}
@@ -0,0 +1,914 @@
+// device tree source compiler for the tests. the blobs in test/ are its output,
+// `cargo test -p dtb -- --ignored` compares them with dtc where it is installed
+//
+// supports the subset of the dts syntax used by the fixtures: labels, phandle and path
+// references, /memreserve/, /bits/, /delete-node/, /delete-property/, /plugin/ overlays and
+// the __symbols__ node of `dtc -@`
+//
+// phandles and the strings block are meant to be laid out the same way as dtc
+
+use std::collections::BTreeSet;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_END: u32 = 9;
+const HEADER_SIZE: usize = 40;
+
+#[derive(Clone, Debug)]
+enum Reference {
+    Label(String),
+    Path(String),
+}
+
+#[derive(Clone, Debug)]
+enum Chunk {
+    Bytes(Vec<u8>),
+    Phandle(Reference),
+    PathString(Reference),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Property {
+    name: String,
+    value: Vec<Chunk>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    name: String,
+    labels: Vec<String>,
+    properties: Vec<Property>,
+    children: Vec<Node>,
+    deleted: bool,
+}
+
+impl Node {
+    fn merge(&mut self, other: Node) {
+        for label in other.labels {
+            if !self.labels.contains(&label) {
+                self.labels.push(label);
+            }
+        }
+        for property in other.properties {
+            match self.properties.iter_mut().find(|p| p.name == property.name) {
+                Some(p) => *p = property,
+                None => self.properties.push(property),
+            }
+        }
+        for child in other.children {
+            if child.deleted {
+                self.children.retain(|c| c.name != child.name);
+                continue;
+            }
+            match self.children.iter_mut().find(|c| c.name == child.name) {
+                Some(c) => c.merge(child),
+                None => self.children.push(child),
+            }
+        }
+    }
+
+    fn find_label(&self, label: &str, path: &str) -> Option<String> {
+        if self.labels.iter().any(|l| l == label) {
+            return Some(path.to_string());
+        }
+        self.children
+            .iter()
+            .find_map(|c| c.find_label(label, &join_path(path, &c.name)))
+    }
+
+    fn find_path(&self, path: &str) -> Option<&Node> {
+        let mut node = self;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node
+                .children
+                .iter()
+                .find(|c| c.name == component || c.name.split('@').next() == Some(component))?;
+        }
+        Some(node)
+    }
+
+    fn find_path_mut(&mut self, path: &str) -> Option<&mut Node> {
+        let mut node = self;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node
+                .children
+                .iter_mut()
+                .find(|c| c.name == component || c.name.split('@').next() == Some(component))?;
+        }
+        Some(node)
+    }
+
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+struct Source {
+    plugin: bool,
+    reservations: Vec<(u64, u64)>,
+    items: Vec<TopLevel>,
+}
+
+enum TopLevel {
+    Root(Node),
+    Reference(Reference, Node),
+    DeleteNode(Reference),
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let line = self.source[..self.position]
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count()
+            + 1;
+        Err(format!("line {}: {}", line, message))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.position += 1;
+            }
+            let rest = &self.source[self.position..];
+            if rest.starts_with(b"//") || rest.starts_with(b"#include") {
+                while self.peek().is_some_and(|c| c != b'\n') {
+                    self.position += 1;
+                }
+            } else if rest.starts_with(b"/*") {
+                match rest.windows(2).position(|w| w == b"*/") {
+                    Some(end) => self.position += end + 2,
+                    None => self.position = self.source.len(),
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.source[self.position..].starts_with(token.as_bytes()) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", token))
+        }
+    }
+
+    fn is_name_char(c: u8) -> bool {
+        c.is_ascii_alphanumeric() || b",._+*#?@-".contains(&c)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.peek().is_some_and(Self::is_name_char) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return self.error("expected a name");
+        }
+        Ok(String::from_utf8_lossy(&self.source[start..self.position]).into_owned())
+    }
+
+    // a label is a name directly followed by ':'
+    fn label(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.position += 1;
+        }
+        if self.position != start && self.peek() == Some(b':') {
+            let label = String::from_utf8_lossy(&self.source[start..self.position]).into_owned();
+            self.position += 1;
+            return Some(label);
+        }
+        self.position = start;
+        None
+    }
+
+    fn reference(&mut self) -> Result<Reference, String> {
+        self.expect("&")?;
+        if self.peek() == Some(b'{') {
+            self.position += 1;
+            let start = self.position;
+            while self.peek().is_some_and(|c| c != b'}') {
+                self.position += 1;
+            }
+            let path = String::from_utf8_lossy(&self.source[start..self.position]).into_owned();
+            self.expect("}")?;
+            return Ok(Reference::Path(path));
+        }
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.position += 1;
+        }
+        Ok(Reference::Label(
+            String::from_utf8_lossy(&self.source[start..self.position]).into_owned(),
+        ))
+    }
+
+    fn parse(&mut self) -> Result<Source, String> {
+        let mut plugin = false;
+        let mut reservations = Vec::new();
+        let mut items = Vec::new();
+        self.expect("/dts-v1/")?;
+        self.expect(";")?;
+        loop {
+            self.skip_whitespace();
+            if self.position >= self.source.len() {
+                break;
+            }
+            if self.eat("/plugin/") {
+                self.expect(";")?;
+                plugin = true;
+            } else if self.eat("/memreserve/") {
+                let address = self.number()?;
+                let size = self.number()?;
+                self.expect(";")?;
+                reservations.push((address, size));
+            } else if self.eat("/delete-node/") {
+                let reference = self.reference()?;
+                self.expect(";")?;
+                items.push(TopLevel::DeleteNode(reference));
+            } else if self.source[self.position..].starts_with(b"&") {
+                let reference = self.reference()?;
+                self.expect("{")?;
+                let node = self.node_body(String::new())?;
+                items.push(TopLevel::Reference(reference, node));
+            } else {
+                let labels = self.labels();
+                self.expect("/")?;
+                self.expect("{")?;
+                let mut node = self.node_body(String::new())?;
+                node.labels = labels;
+                items.push(TopLevel::Root(node));
+            }
+        }
+        Ok(Source {
+            plugin,
+            reservations,
+            items,
+        })
+    }
+
+    fn labels(&mut self) -> Vec<String> {
+        let mut labels = Vec::new();
+        while let Some(label) = self.label() {
+            labels.push(label);
+        }
+        labels
+    }
+
+    // parses the inside of a node after '{' up to and including "};"
+    fn node_body(&mut self, name: String) -> Result<Node, String> {
+        let mut node = Node {
+            name,
+            ..Default::default()
+        };
+        loop {
+            if self.eat("}") {
+                self.expect(";")?;
+                return Ok(node);
+            }
+            if self.eat("/delete-property/") {
+                let name = self.name()?;
+                self.expect(";")?;
+                node.properties.retain(|p| p.name != name);
+                continue;
+            }
+            if self.eat("/delete-node/") {
+                let name = self.name()?;
+                self.expect(";")?;
+                node.children.retain(|c| c.name != name);
+                node.children.push(Node {
+                    name,
+                    deleted: true,
+                    ..Default::default()
+                });
+                continue;
+            }
+            let labels = self.labels();
+            let name = self.name()?;
+            if self.eat("{") {
+                let mut child = self.node_body(name)?;
+                child.labels = labels;
+                let mut parent = Node::default();
+                parent.children.push(child);
+                node.merge(parent);
+            } else if self.eat("=") {
+                let value = self.property_value()?;
+                node.merge(Node {
+                    properties: vec![Property { name, value }],
+                    ..Default::default()
+                });
+            } else {
+                self.expect(";")?;
+                node.merge(Node {
+                    properties: vec![Property {
+                        name,
+                        value: Vec::new(),
+                    }],
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    fn property_value(&mut self) -> Result<Vec<Chunk>, String> {
+        let mut value = Vec::new();
+        loop {
+            self.labels();
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'"') => value.push(Chunk::Bytes(self.string()?)),
+                Some(b'<') => {
+                    self.position += 1;
+                    self.cells(4, &mut value)?;
+                }
+                Some(b'[') => {
+                    self.position += 1;
+                    value.push(Chunk::Bytes(self.bytes()?));
+                }
+                Some(b'&') => value.push(Chunk::PathString(self.reference()?)),
+                Some(b'/') if self.eat("/bits/") => {
+                    let bits = self.number()?;
+                    self.expect("<")?;
+                    self.cells(bits as usize / 8, &mut value)?;
+                }
+                _ => return self.error("unexpected property value"),
+            }
+            if self.eat(";") {
+                return Ok(value);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        self.expect("\"")?;
+        let mut string = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.position += 1;
+                    let c = self.peek().unwrap_or(b'\\');
+                    string.push(match c {
+                        b'n' => b'\n',
+                        b't' => b'\t',
+                        b'0' => 0,
+                        c => c,
+                    });
+                }
+                Some(c) => string.push(c),
+                None => return self.error("unterminated string"),
+            }
+            self.position += 1;
+        }
+        self.position += 1;
+        string.push(0);
+        Ok(string)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(b']') {
+                self.position += 1;
+                return Ok(bytes);
+            }
+            let digits = self.source.get(self.position..self.position + 2);
+            let byte = digits
+                .and_then(|d| core::str::from_utf8(d).ok())
+                .and_then(|d| u8::from_str_radix(d, 16).ok());
+            match byte {
+                Some(b) => bytes.push(b),
+                None => return self.error("invalid byte string"),
+            }
+            self.position += 2;
+        }
+    }
+
+    fn cells(&mut self, width: usize, value: &mut Vec<Chunk>) -> Result<(), String> {
+        loop {
+            self.labels();
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'>') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(b'&') => {
+                    if width != 4 {
+                        return self.error("phandle references must be 32-bit cells");
+                    }
+                    value.push(Chunk::Phandle(self.reference()?));
+                }
+                _ => {
+                    let number = self.expression()?;
+                    let bytes = number.to_be_bytes();
+                    value.push(Chunk::Bytes(bytes[8 - width..].to_vec()));
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self) -> Result<u64, String> {
+        self.skip_whitespace();
+        if !self.eat("(") {
+            return self.number();
+        }
+        let mut result = self.expression()?;
+        loop {
+            self.skip_whitespace();
+            if self.eat(")") {
+                return Ok(result);
+            }
+            let operator = if self.eat("<<") {
+                "<<"
+            } else if self.eat(">>") {
+                ">>"
+            } else if self.eat("|") {
+                "|"
+            } else if self.eat("&") {
+                "&"
+            } else if self.eat("+") {
+                "+"
+            } else if self.eat("-") {
+                "-"
+            } else if self.eat("*") {
+                "*"
+            } else {
+                return self.error("unsupported operator");
+            };
+            let rhs = self.expression()?;
+            result = match operator {
+                "<<" => result << rhs,
+                ">>" => result >> rhs,
+                "|" => result | rhs,
+                "&" => result & rhs,
+                "+" => result.wrapping_add(rhs),
+                "-" => result.wrapping_sub(rhs),
+                _ => result.wrapping_mul(rhs),
+            };
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        self.skip_whitespace();
+        if self.peek() == Some(b'\'') {
+            let c = self.source.get(self.position + 1).copied();
+            self.position += 3;
+            return c
+                .map(u64::from)
+                .ok_or_else(|| "invalid char literal".to_string());
+        }
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.position += 1;
+        }
+        let text = core::str::from_utf8(&self.source[start..self.position])
+            .unwrap()
+            .trim_end_matches(['U', 'L', 'u', 'l']);
+        let parsed = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+            u64::from_str_radix(hex, 16)
+        } else if text.len() > 1 && text.starts_with('0') {
+            u64::from_str_radix(&text[1..], 8)
+        } else {
+            text.parse()
+        };
+        match parsed {
+            Ok(n) => Ok(n),
+            Err(_) => {
+                self.position = start;
+                self.error(&format!("invalid number '{}'", text))
+            }
+        }
+    }
+}
+
+struct Fixup {
+    path: String,
+    property: String,
+    offset: usize,
+}
+
+struct Compiler {
+    plugin: bool,
+    // dtc takes the lowest phandle which is not used yet
+    next_phandle: u32,
+    used_phandles: BTreeSet<u32>,
+    // label -> fixup locations of unresolved references (overlays only), in the order of the
+    // first reference like dtc
+    fixups: Vec<(String, Vec<Fixup>)>,
+    local_fixups: Vec<Fixup>,
+}
+
+impl Compiler {
+    fn resolve_path(root: &Node, reference: &Reference) -> Option<String> {
+        match reference {
+            Reference::Label(label) => root.find_label(label, "/"),
+            Reference::Path(path) => root.find_path(path).map(|_| path.clone()),
+        }
+    }
+
+    fn phandle_of(&mut self, root: &mut Node, path: &str) -> u32 {
+        let node = root.find_path_mut(path).unwrap();
+        if let Some(p) = node.property("phandle")
+            && let Some(Chunk::Bytes(b)) = p.value.first()
+        {
+            return u32::from_be_bytes(b[..4].try_into().unwrap());
+        }
+        while self.used_phandles.contains(&self.next_phandle) {
+            self.next_phandle += 1;
+        }
+        let phandle = self.next_phandle;
+        self.used_phandles.insert(phandle);
+        node.properties.push(Property {
+            name: "phandle".to_string(),
+            value: vec![Chunk::Bytes(phandle.to_be_bytes().to_vec())],
+        });
+        phandle
+    }
+
+    // replaces every reference chunk with its resolved bytes
+    fn resolve(&mut self, root: &mut Node, path: String) -> Result<(), String> {
+        let node = root.find_path_mut(&path).unwrap();
+        let properties = node.properties.clone();
+        let children: Vec<String> = node.children.iter().map(|c| c.name.clone()).collect();
+        let mut resolved = Vec::new();
+        for property in properties {
+            let mut bytes = Vec::new();
+            for chunk in &property.value {
+                match chunk {
+                    Chunk::Bytes(b) => bytes.extend_from_slice(b),
+                    Chunk::PathString(reference) => match Self::resolve_path(root, reference) {
+                        Some(target) => {
+                            bytes.extend_from_slice(target.as_bytes());
+                            bytes.push(0);
+                        }
+                        None => return Err(format!("unresolved path reference {:?}", reference)),
+                    },
+                    Chunk::Phandle(reference) => match Self::resolve_path(root, reference) {
+                        Some(target) => {
+                            if self.plugin {
+                                self.local_fixups.push(Fixup {
+                                    path: path.clone(),
+                                    property: property.name.clone(),
+                                    offset: bytes.len(),
+                                });
+                            }
+                            let phandle = self.phandle_of(root, &target);
+                            bytes.extend_from_slice(&phandle.to_be_bytes());
+                        }
+                        None => match reference {
+                            Reference::Label(label) if self.plugin => {
+                                let index = match self.fixups.iter().position(|(l, _)| l == label) {
+                                    Some(index) => index,
+                                    None => {
+                                        self.fixups.push((label.clone(), Vec::new()));
+                                        self.fixups.len() - 1
+                                    }
+                                };
+                                self.fixups[index].1.push(Fixup {
+                                    path: path.clone(),
+                                    property: property.name.clone(),
+                                    offset: bytes.len(),
+                                });
+                                bytes.extend_from_slice(&0xffff_ffffu32.to_be_bytes());
+                            }
+                            _ => return Err(format!("unresolved reference {:?}", reference)),
+                        },
+                    },
+                }
+            }
+            resolved.push(Property {
+                name: property.name,
+                value: vec![Chunk::Bytes(bytes)],
+            });
+        }
+        let node = root.find_path_mut(&path).unwrap();
+        for property in resolved {
+            // phandle properties may have been appended while resolving
+            if let Some(p) = node.properties.iter_mut().find(|p| p.name == property.name) {
+                *p = property;
+            }
+        }
+        for child in children {
+            self.resolve(root, join_path(&path, &child))?;
+        }
+        Ok(())
+    }
+
+    fn collect_labelled(node: &Node, path: &str, paths: &mut Vec<String>) {
+        if !node.labels.is_empty() {
+            paths.push(path.to_string());
+        }
+        for child in &node.children {
+            Self::collect_labelled(child, &join_path(path, &child.name), paths);
+        }
+    }
+
+    fn collect_symbols(node: &Node, path: &str, symbols: &mut Vec<Property>) {
+        for label in &node.labels {
+            let mut value = path.as_bytes().to_vec();
+            value.push(0);
+            symbols.push(Property {
+                name: label.clone(),
+                value: vec![Chunk::Bytes(value)],
+            });
+        }
+        for child in &node.children {
+            Self::collect_symbols(child, &join_path(path, &child.name), symbols);
+        }
+    }
+
+    fn add_local_fixup(root: &mut Node, fixup: &Fixup) {
+        let mut node = root;
+        for component in fixup.path.split('/').filter(|c| !c.is_empty()) {
+            let index = match node.children.iter().position(|c| c.name == component) {
+                Some(i) => i,
+                None => {
+                    node.children.push(Node {
+                        name: component.to_string(),
+                        ..Default::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[index];
+        }
+        let offset = (fixup.offset as u32).to_be_bytes().to_vec();
+        match node
+            .properties
+            .iter_mut()
+            .find(|p| p.name == fixup.property)
+        {
+            Some(p) => p.value.push(Chunk::Bytes(offset)),
+            None => node.properties.push(Property {
+                name: fixup.property.clone(),
+                value: vec![Chunk::Bytes(offset)],
+            }),
+        }
+    }
+}
+
+fn collect_phandles(node: &Node, phandles: &mut BTreeSet<u32>) {
+    if let Some(Chunk::Bytes(b)) = node.property("phandle").and_then(|p| p.value.first())
+        && let Ok(b) = b.as_slice().try_into()
+    {
+        phandles.insert(u32::from_be_bytes(b));
+    }
+    for child in &node.children {
+        collect_phandles(child, phandles);
+    }
+}
+
+/// compiles a device tree source into a version 17 flattened device tree blob
+///
+/// `symbols` corresponds to dtc's `-@` option and emits the __symbols__ node
+pub fn compile(source: &str, symbols: bool) -> Result<Vec<u8>, String> {
+    let mut parser = Parser {
+        source: source.as_bytes(),
+        position: 0,
+    };
+    let Source {
+        plugin,
+        reservations,
+        items,
+    } = parser.parse()?;
+
+    let mut root = Node::default();
+    let mut fragment = 0;
+    for item in items {
+        match item {
+            TopLevel::Root(node) => root.merge(node),
+            TopLevel::Reference(reference, body) if plugin => {
+                let mut target = Property {
+                    name: "target".to_string(),
+                    value: vec![Chunk::Phandle(reference.clone())],
+                };
+                if let Reference::Path(path) = &reference {
+                    let mut value = path.as_bytes().to_vec();
+                    value.push(0);
+                    target = Property {
+                        name: "target-path".to_string(),
+                        value: vec![Chunk::Bytes(value)],
+                    };
+                }
+                let mut overlay = body;
+                overlay.name = "__overlay__".to_string();
+                let mut wrapper = Node::default();
+                wrapper.children.push(Node {
+                    name: format!("fragment@{}", fragment),
+                    properties: vec![target],
+                    children: vec![overlay],
+                    ..Default::default()
+                });
+                fragment += 1;
+                root.merge(wrapper);
+            }
+            TopLevel::Reference(reference, body) => {
+                let path = Compiler::resolve_path(&root, &reference)
+                    .ok_or_else(|| format!("unresolved node reference {:?}", reference))?;
+                root.find_path_mut(&path).unwrap().merge(body);
+            }
+            TopLevel::DeleteNode(reference) => {
+                let path = Compiler::resolve_path(&root, &reference)
+                    .ok_or_else(|| format!("unresolved node reference {:?}", reference))?;
+                let (parent, name) = path.rsplit_once('/').unwrap();
+                let parent = if parent.is_empty() { "/" } else { parent };
+                let name = name.to_string();
+                root.find_path_mut(parent)
+                    .unwrap()
+                    .children
+                    .retain(|c| c.name != name);
+            }
+        }
+    }
+
+    let mut used_phandles = BTreeSet::new();
+    collect_phandles(&root, &mut used_phandles);
+    let mut compiler = Compiler {
+        plugin,
+        next_phandle: 1,
+        used_phandles,
+        fixups: Vec::new(),
+        local_fixups: Vec::new(),
+    };
+    compiler.resolve(&mut root, "/".to_string())?;
+
+    if symbols {
+        // like dtc, every labelled node gets a phandle so that overlays can refer to it
+        let mut labelled = Vec::new();
+        Compiler::collect_labelled(&root, "/", &mut labelled);
+        for path in labelled {
+            compiler.phandle_of(&mut root, &path);
+        }
+        let mut properties = Vec::new();
+        Compiler::collect_symbols(&root, "/", &mut properties);
+        if !properties.is_empty() {
+            root.children.push(Node {
+                name: "__symbols__".to_string(),
+                properties,
+                ..Default::default()
+            });
+        }
+    }
+    if plugin {
+        if !compiler.fixups.is_empty() {
+            let mut node = Node {
+                name: "__fixups__".to_string(),
+                ..Default::default()
+            };
+            for (label, locations) in &compiler.fixups {
+                let mut value = Vec::new();
+                for fixup in locations {
+                    value.extend_from_slice(
+                        format!("{}:{}:{}", fixup.path, fixup.property, fixup.offset).as_bytes(),
+                    );
+                    value.push(0);
+                }
+                node.properties.push(Property {
+                    name: label.clone(),
+                    value: vec![Chunk::Bytes(value)],
+                });
+            }
+            root.children.push(node);
+        }
+        if !compiler.local_fixups.is_empty() {
+            let mut node = Node {
+                name: "__local_fixups__".to_string(),
+                ..Default::default()
+            };
+            for fixup in &compiler.local_fixups {
+                Compiler::add_local_fixup(&mut node, fixup);
+            }
+            root.children.push(node);
+        }
+    }
+
+    Ok(flatten(&root, &reservations))
+}
+
+fn flatten(root: &Node, reservations: &[(u64, u64)]) -> Vec<u8> {
+    let mut structure = Vec::new();
+    let mut strings: Vec<u8> = Vec::new();
+    flatten_node(root, &mut structure, &mut strings);
+    structure.extend_from_slice(&FDT_END.to_be_bytes());
+
+    let mut reserve = Vec::new();
+    for (address, size) in reservations.iter().chain([(0, 0)].iter()) {
+        reserve.extend_from_slice(&address.to_be_bytes());
+        reserve.extend_from_slice(&size.to_be_bytes());
+    }
+
+    let off_mem_rsvmap = HEADER_SIZE;
+    let off_dt_struct = off_mem_rsvmap + reserve.len();
+    let off_dt_strings = off_dt_struct + structure.len();
+    let total_size = off_dt_strings + strings.len();
+
+    let mut blob = Vec::with_capacity(total_size);
+    for field in [
+        FDT_MAGIC,
+        total_size as u32,
+        off_dt_struct as u32,
+        off_dt_strings as u32,
+        off_mem_rsvmap as u32,
+        17,
+        16,
+        0,
+        strings.len() as u32,
+        structure.len() as u32,
+    ] {
+        blob.extend_from_slice(&field.to_be_bytes());
+    }
+    blob.extend_from_slice(&reserve);
+    blob.extend_from_slice(&structure);
+    blob.extend_from_slice(&strings);
+    blob
+}
+
+fn flatten_node(node: &Node, structure: &mut Vec<u8>, strings: &mut Vec<u8>) {
+    structure.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+    structure.extend_from_slice(node.name.as_bytes());
+    structure.push(0);
+    pad(structure);
+    for property in &node.properties {
+        let offset = string_offset(strings, &property.name);
+        let value: Vec<u8> = property
+            .value
+            .iter()
+            .flat_map(|c| match c {
+                Chunk::Bytes(b) => b.clone(),
+                _ => unreachable!("references are resolved before flattening"),
+            })
+            .collect();
+        structure.extend_from_slice(&FDT_PROP.to_be_bytes());
+        structure.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        structure.extend_from_slice(&offset.to_be_bytes());
+        structure.extend_from_slice(&value);
+        pad(structure);
+    }
+    // a /delete-node/ which did not match any node is left as a marker
+    for child in node.children.iter().filter(|c| !c.deleted) {
+        flatten_node(child, structure, strings);
+    }
+    structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+}
+
+// like dtc, a name which is the tail of a string already in the block reuses it
+fn string_offset(strings: &mut Vec<u8>, name: &str) -> u32 {
+    let mut needle = name.as_bytes().to_vec();
+    needle.push(0);
+    let offset = match strings.windows(needle.len()).position(|w| w == needle) {
+        Some(offset) => offset,
+        None => {
+            strings.extend_from_slice(&needle);
+            strings.len() - needle.len()
+        }
+    };
+    offset as u32
+}
+
+fn pad(buffer: &mut Vec<u8>) {
+    while !buffer.len().is_multiple_of(4) {
+        buffer.push(0);
+    }
+}
@@ -0,0 +1,395 @@
+// in-memory device tree built from the flattened device tree once a heap is available
+
+use alloc::{string::String, vec::Vec};
+
+use core::ops::ControlFlow;
+
+use crate::dtb_parser::{DtbParser, DtbVisitor};
+use crate::error::DtbError;
+
+/// property value decoded by the property name and its contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceProperty<'a> {
+    String(&'a str),
+    StringList(Vec<&'a str>),
+    U32(u32),
+    Cells(Vec<u32>),
+    Bytes(&'a [u8]),
+    Empty,
+}
+
+impl<'a> DeviceProperty<'a> {
+    // properties whose type is defined by the devicetree specification
+    const U32_PROPERTIES: [&'static str; 12] = [
+        "#address-cells",
+        "#size-cells",
+        "#interrupt-cells",
+        "#clock-cells",
+        "#gpio-cells",
+        "#msi-cells",
+        "#pwm-cells",
+        "phandle",
+        "linux,phandle",
+        "interrupt-parent",
+        "virtual-reg",
+        "cache-level",
+    ];
+    const STRING_LIST_PROPERTIES: [&'static str; 6] = [
+        "compatible",
+        "clock-names",
+        "clock-output-names",
+        "interrupt-names",
+        "reg-names",
+        "pinctrl-names",
+    ];
+
+    fn decode(name: &str, value: &'a [u8]) -> Self {
+        if value.is_empty() {
+            return Self::Empty;
+        }
+        if Self::U32_PROPERTIES.contains(&name) && value.len() == size_of::<u32>() {
+            return Self::U32(u32::from_be_bytes(value.try_into().unwrap()));
+        }
+        if let Some(mut strings) = Self::decode_strings(value) {
+            if strings.len() == 1 && !Self::STRING_LIST_PROPERTIES.contains(&name) {
+                return Self::String(strings.pop().unwrap());
+            }
+            return Self::StringList(strings);
+        }
+        if value.len().is_multiple_of(size_of::<u32>()) {
+            return Self::Cells(
+                value
+                    .as_chunks::<4>()
+                    .0
+                    .iter()
+                    .map(|cell| u32::from_be_bytes(*cell))
+                    .collect(),
+            );
+        }
+        Self::Bytes(value)
+    }
+
+    // returns Some only when the value is a list of printable null terminated strings
+    fn decode_strings(value: &'a [u8]) -> Option<Vec<&'a str>> {
+        let (last, body) = value.split_last()?;
+        if *last != 0 {
+            return None;
+        }
+        body.split(|&c| c == 0)
+            .map(|s| {
+                if s.is_empty() || !s.iter().all(|c| c.is_ascii_graphic() || *c == b' ') {
+                    return None;
+                }
+                core::str::from_utf8(s).ok()
+            })
+            .collect()
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Self::U32(value) => Some(*value),
+            Self::Cells(cells) if cells.len() == 1 => Some(cells[0]),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Self::String(s) => Some(s),
+            Self::StringList(list) => list.first().copied(),
+            _ => None,
+        }
+    }
+
+    pub fn as_cells(&self) -> Option<&[u32]> {
+        match self {
+            Self::U32(value) => Some(core::slice::from_ref(value)),
+            Self::Cells(cells) => Some(cells),
+            _ => None,
+        }
+    }
+
+    pub fn contains_str(&self, target: &str) -> bool {
+        match self {
+            Self::String(s) => *s == target,
+            Self::StringList(list) => list.contains(&target),
+            _ => false,
+        }
+    }
+}
+
+struct NodeEntry<'a> {
+    name: &'a str,
+    full_name: String,
+    properties: Vec<(&'a str, DeviceProperty<'a>)>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+struct TreeBuilder<'a> {
+    nodes: Vec<NodeEntry<'a>>,
+    current: Option<usize>,
+}
+
+impl<'a> DtbVisitor<'a> for TreeBuilder<'a> {
+    fn begin_node(&mut self, name: &'a str, _depth: usize) -> ControlFlow<()> {
+        let full_name = match self.current {
+            None => String::from("/"),
+            Some(parent) => {
+                let mut path = self.nodes[parent].full_name.clone();
+                if parent != 0 {
+                    path.push('/');
+                }
+                path.push_str(name);
+                path
+            }
+        };
+        let index = self.nodes.len();
+        self.nodes.push(NodeEntry {
+            name,
+            full_name,
+            properties: Vec::new(),
+            parent: self.current,
+            children: Vec::new(),
+        });
+        if let Some(parent) = self.current {
+            self.nodes[parent].children.push(index);
+        }
+        self.current = Some(index);
+        ControlFlow::Continue(())
+    }
+
+    fn property(&mut self, name: &'a str, value: &'a [u8]) -> ControlFlow<()> {
+        if let Some(node) = self.current {
+            self.nodes[node]
+                .properties
+                .push((name, DeviceProperty::decode(name, value)));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn end_node(&mut self, _depth: usize) -> ControlFlow<()> {
+        self.current = self.current.and_then(|node| self.nodes[node].parent);
+        ControlFlow::Continue(())
+    }
+}
+
+/// device tree held on the heap
+///
+/// nodes are stored in the order of the structure block, the root node is always the first one
+pub struct DeviceTree<'a> {
+    nodes: Vec<NodeEntry<'a>>,
+}
+
+impl<'a> DeviceTree<'a> {
+    pub fn new(parser: &'a DtbParser<'a>) -> Result<Self, DtbError> {
+        let mut builder = TreeBuilder {
+            nodes: Vec::new(),
+            current: None,
+        };
+        // the builder never breaks, and walk checks that the nodes are balanced
+        let _ = parser.walk(&mut builder)?;
+        Ok(Self {
+            nodes: builder.nodes,
+        })
+    }
+
+    pub fn root(&self) -> DeviceNode<'_, 'a> {
+        DeviceNode {
+            tree: self,
+            index: 0,
+        }
+    }
+
+    /// iterates over every node in the order of the structure block
+    pub fn nodes(&self) -> impl Iterator<Item = DeviceNode<'_, 'a>> {
+        (0..self.nodes.len()).map(|index| DeviceNode { tree: self, index })
+    }
+
+    /// finds a node by its absolute path
+    ///
+    /// the unit address can be omitted when it is unambiguous, e.g. "/soc/serial@7d001000"
+    pub fn find_by_path(&self, path: &str) -> Option<DeviceNode<'_, 'a>> {
+        let mut node = self.root();
+        for component in path.strip_prefix('/')?.split('/') {
+            if component.is_empty() {
+                continue;
+            }
+            node = node.find_child(component)?;
+        }
+        Some(node)
+    }
+
+    pub fn find_compatible<'t>(
+        &'t self,
+        compatible: &'t str,
+    ) -> impl Iterator<Item = DeviceNode<'t, 'a>> + 't {
+        self.nodes()
+            .filter(move |node| node.is_compatible(compatible))
+    }
+
+    pub fn find_by_phandle(&self, phandle: u32) -> Option<DeviceNode<'_, 'a>> {
+        self.nodes().find(|node| node.phandle() == Some(phandle))
+    }
+}
+
+/// reference to a node of the DeviceTree
+#[derive(Clone, Copy)]
+pub struct DeviceNode<'t, 'a> {
+    tree: &'t DeviceTree<'a>,
+    index: usize,
+}
+
+impl<'t, 'a> DeviceNode<'t, 'a> {
+    fn entry(&self) -> &'t NodeEntry<'a> {
+        &self.tree.nodes[self.index]
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.entry().name
+    }
+
+    /// the absolute path of this node
+    pub fn full_name(&self) -> &'t str {
+        &self.entry().full_name
+    }
+
+    pub fn parent(&self) -> Option<DeviceNode<'t, 'a>> {
+        self.entry().parent.map(|index| DeviceNode {
+            tree: self.tree,
+            index,
+        })
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = DeviceNode<'t, 'a>> + use<'t, 'a> {
+        let tree = self.tree;
+        self.entry()
+            .children
+            .iter()
+            .map(move |&index| DeviceNode { tree, index })
+    }
+
+    /// finds a direct child by name, the unit address is optional
+    pub fn find_child(&self, name: &str) -> Option<DeviceNode<'t, 'a>> {
+        self.children()
+            .find(|child| child.name() == name)
+            .or_else(|| {
+                if name.contains('@') {
+                    return None;
+                }
+                self.children()
+                    .find(|child| child.name().split('@').next() == Some(name))
+            })
+    }
+
+    pub fn properties(
+        &self,
+    ) -> impl Iterator<Item = (&'a str, &'t DeviceProperty<'a>)> + use<'t, 'a> {
+        self.entry()
+            .properties
+            .iter()
+            .map(|(name, property)| (*name, property))
+    }
+
+    pub fn property(&self, name: &str) -> Option<&'t DeviceProperty<'a>> {
+        self.properties()
+            .find(|(property_name, _)| *property_name == name)
+            .map(|(_, property)| property)
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible")
+            .is_some_and(|property| property.contains_str(compatible))
+    }
+
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle")
+            .or_else(|| self.property("linux,phandle"))
+            .and_then(DeviceProperty::as_u32)
+    }
+
+    /// "#address-cells" of this node, 2 when it is not present
+    pub fn address_cells(&self) -> u32 {
+        self.property("#address-cells")
+            .and_then(DeviceProperty::as_u32)
+            .unwrap_or(2)
+    }
+
+    /// "#size-cells" of this node, 1 when it is not present
+    pub fn size_cells(&self) -> u32 {
+        self.property("#size-cells")
+            .and_then(DeviceProperty::as_u32)
+            .unwrap_or(1)
+    }
+}
+
+impl core::fmt::Debug for DeviceNode<'_, '_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceNode")
+            .field("full_name", &self.full_name())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_device_tree() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+        let tree = DeviceTree::new(&parser).unwrap();
+
+        let root = tree.root();
+        assert_eq!(root.full_name(), "/");
+        assert!(root.parent().is_none());
+        assert_eq!(root.address_cells(), 2);
+        assert_eq!(root.size_cells(), 1);
+        assert!(root.is_compatible("brcm,bcm2712"));
+
+        let uart = tree.find_by_path("/soc/serial@7d001000").unwrap();
+        assert_eq!(uart.full_name(), "/soc@107c000000/serial@7d001000");
+        assert_eq!(uart.parent().unwrap().name(), "soc@107c000000");
+        assert_eq!(
+            uart.property("compatible"),
+            Some(&DeviceProperty::StringList(vec![
+                "arm,pl011",
+                "arm,primecell"
+            ]))
+        );
+        assert_eq!(
+            uart.property("reg"),
+            Some(&DeviceProperty::Cells(vec![0x7d00_1000, 0x200]))
+        );
+        assert_eq!(
+            uart.property("status").and_then(DeviceProperty::as_str),
+            Some("okay")
+        );
+        assert!(tree.find_by_path("/soc/serial@7d001001").is_none());
+
+        let cpus = tree.find_by_path("/cpus").unwrap();
+        assert_eq!(
+            cpus.children()
+                .filter(|cpu| cpu.property("device_type") == Some(&DeviceProperty::String("cpu")))
+                .count(),
+            4
+        );
+        assert_eq!(tree.find_compatible("arm,gic-400").count(), 1);
+        assert_eq!(tree.find_compatible("arm,pl011").count(), 1);
+
+        let gic = tree.find_compatible("arm,gic-400").next().unwrap();
+        let interrupt_parent = root
+            .property("interrupt-parent")
+            .and_then(DeviceProperty::as_u32)
+            .unwrap();
+        assert_eq!(
+            tree.find_by_phandle(interrupt_parent).unwrap().full_name(),
+            gic.full_name()
+        );
+        assert_eq!(
+            gic.property("interrupt-controller"),
+            Some(&DeviceProperty::Empty)
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// clock rates resolved through 'clocks' and the fixed clock providers
+
+use super::*;
+
+/// a clock consumed by a node, one entry of 'clocks'
+#[derive(Clone, Copy, Debug)]
+pub struct Clock<'a> {
+    provider: DtbNode<'a>,
+    cells: [u32; Clock::MAX_CELLS],
+    len: usize,
+}
+
+impl<'a> Clock<'a> {
+    // '#clock-cells' is 0 or 1 on almost every provider
+    const MAX_CELLS: usize = 4;
+
+    /// the node which has '#clock-cells'
+    pub fn provider(&self) -> DtbNode<'a> {
+        self.provider
+    }
+
+    /// clock specifier decoded by the '#clock-cells' of the provider
+    pub fn specifier(&self) -> &[u32] {
+        &self.cells[..self.len]
+    }
+
+    /// resolves the rate in Hz
+    ///
+    /// "fixed-clock" and "fixed-factor-clock" are followed to the root of the clock tree. the rate
+    /// of any other provider is only known when it sets the clock with 'assigned-clock-rates'
+    pub fn rate(&self) -> Result<u64, DtbError> {
+        self.rate_at(0)
+    }
+
+    // `depth` counts the fixed-factor-clock nodes followed so far
+    fn rate_at(&self, depth: usize) -> Result<u64, DtbError> {
+        let provider = self.provider;
+        if depth == DtbNode::MAX_DEPTH {
+            return Err(provider.error(DtbErrorKind::TooDeep));
+        }
+        let read = |name| {
+            provider
+                .property(name)?
+                .ok_or_else(|| provider.error(DtbErrorKind::NotFound))
+        };
+        let invalid = || provider.error(DtbErrorKind::InvalidProperty);
+        if provider.is_compatible(DtbNode::COMPATIBLE_FIXED_CLOCK)? {
+            return read(DtbNode::PROP_CLOCK_FREQUENCY)?
+                .as_u64()
+                .ok_or_else(invalid);
+        }
+        if provider.is_compatible(DtbNode::COMPATIBLE_FIXED_FACTOR_CLOCK)? {
+            let mult = read(DtbNode::PROP_CLOCK_MULT)?
+                .as_u32()
+                .ok_or_else(invalid)?;
+            let div = read(DtbNode::PROP_CLOCK_DIV)?
+                .as_u32()
+                .filter(|div| *div != 0)
+                .ok_or_else(invalid)?;
+            let parent = provider
+                .clocks()?
+                .next()
+                .ok_or_else(|| provider.error(DtbErrorKind::NotFound))??;
+            return parent
+                .rate_at(depth + 1)?
+                .checked_mul(u64::from(mult))
+                .map(|rate| rate / u64::from(div))
+                .ok_or_else(|| provider.error(DtbErrorKind::Overflow));
+        }
+        // e.g. the RP1 clock controller sets the rate of the UART clock in its own node
+        let Some(rates) = provider.property(DtbNode::PROP_ASSIGNED_CLOCK_RATES)? else {
+            return Err(provider.error(DtbErrorKind::UnsupportedClock));
+        };
+        let mut rates = rates.as_cells().ok_or_else(invalid)?;
+        for assigned in provider.clock_list(DtbNode::PROP_ASSIGNED_CLOCKS)? {
+            let assigned = assigned?;
+            // a rate of 0 leaves the clock as it is
+            let rate = rates.next().unwrap_or(0);
+            if assigned.provider.address == provider.address
+                && assigned.specifier() == self.specifier()
+                && rate != 0
+            {
+                return Ok(u64::from(rate));
+            }
+        }
+        Err(provider.error(DtbErrorKind::UnsupportedClock))
+    }
+}
+
+impl<'a> DtbNode<'a> {
+    const PROP_CLOCKS: &'static str = "clocks";
+    const PROP_CLOCK_NAMES: &'static str = "clock-names";
+    const PROP_CLOCK_CELLS: &'static str = "#clock-cells";
+    const PROP_CLOCK_FREQUENCY: &'static str = "clock-frequency";
+    const PROP_CLOCK_MULT: &'static str = "clock-mult";
+    const PROP_CLOCK_DIV: &'static str = "clock-div";
+    const PROP_ASSIGNED_CLOCKS: &'static str = "assigned-clocks";
+    const PROP_ASSIGNED_CLOCK_RATES: &'static str = "assigned-clock-rates";
+    const COMPATIBLE_FIXED_CLOCK: &'static str = "fixed-clock";
+    const COMPATIBLE_FIXED_FACTOR_CLOCK: &'static str = "fixed-factor-clock";
+
+    /// iterates over the entries of 'clocks'
+    pub fn clocks(&self) -> Result<ClockIter<'a>, DtbError> {
+        self.clock_list(Self::PROP_CLOCKS)
+    }
+
+    /// finds the entry of 'clocks' at the position of `name` in 'clock-names'
+    pub fn clock(&self, name: &str) -> Result<Option<Clock<'a>>, DtbError> {
+        let Some(names) = self.property(Self::PROP_CLOCK_NAMES)? else {
+            return Ok(None);
+        };
+        for (index, clock_name) in names.as_str_list().enumerate() {
+            if clock_name? == name {
+                return self
+                    .clocks()?
+                    .nth(index)
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?
+                    .map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// rate in Hz of the clock named `name`, None when the node has no such clock
+    pub fn clock_rate(&self, name: &str) -> Result<Option<u64>, DtbError> {
+        self.clock(name)?.map(|clock| clock.rate()).transpose()
+    }
+
+    fn clock_list(&self, name: &str) -> Result<ClockIter<'a>, DtbError> {
+        let cells = match self.property(name)? {
+            Some(clocks) => Some(
+                clocks
+                    .as_cells()
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?,
+            ),
+            None => None,
+        };
+        Ok(ClockIter { node: *self, cells })
+    }
+}
+
+/// iterator over the clocks of a node
+pub struct ClockIter<'a> {
+    node: DtbNode<'a>,
+    cells: Option<Cells<'a>>,
+}
+
+impl<'a> ClockIter<'a> {
+    fn next_internal(&mut self) -> Result<Option<Clock<'a>>, DtbError> {
+        let Some(cells) = self.cells.as_mut().filter(|cells| !cells.is_empty()) else {
+            return Ok(None);
+        };
+        let invalid = || self.node.error(DtbErrorKind::InvalidProperty);
+        let phandle = cells.next().ok_or_else(invalid)?;
+        let provider = self
+            .node
+            .parser
+            .find_node_by_phandle(phandle)?
+            .ok_or_else(|| self.node.error(DtbErrorKind::UnknownPhandle))?;
+        let len = provider
+            .property(DtbNode::PROP_CLOCK_CELLS)?
+            .ok_or_else(|| provider.error(DtbErrorKind::NotFound))?
+            .as_u32()
+            .ok_or_else(|| provider.error(DtbErrorKind::InvalidProperty))?
+            as usize;
+        if len > Clock::MAX_CELLS {
+            return Err(provider.error(DtbErrorKind::CapacityExceeded));
+        }
+        let mut clock = Clock {
+            provider,
+            cells: [0; Clock::MAX_CELLS],
+            len,
+        };
+        for cell in &mut clock.cells[..len] {
+            *cell = cells.next().ok_or_else(invalid)?;
+        }
+        Ok(Some(clock))
+    }
+}
+
+impl<'a> Iterator for ClockIter<'a> {
+    type Item = Result<Clock<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_internal();
+        if result.is_err() {
+            self.cells = None;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_clock_rates() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let uart10 = parser.find_node_by_alias("serial10").unwrap().unwrap();
+        assert_eq!(uart10.clock_rate("uartclk"), Ok(Some(4400_0000)));
+        assert_eq!(uart10.clock_rate("apb_pclk"), Ok(Some(7_5000_0000)));
+        assert_eq!(uart10.clock_rate("missing"), Ok(None));
+        assert_eq!(uart10.clocks().unwrap().count(), 2);
+
+        // rates assigned by the RP1 clock controller
+        let uart0 = parser.find_node_by_alias("uart0").unwrap().unwrap();
+        let uartclk = uart0.clock("uartclk").unwrap().unwrap();
+        assert_eq!(uartclk.specifier(), [13]);
+        assert_eq!(uartclk.rate(), Ok(4800_0000));
+        assert_eq!(uart0.clock_rate("apb_pclk"), Ok(Some(2_0000_0000)));
+
+        // xosc * 4 / 1 and then / 2
+        let eth = parser
+            .find_node_by_path("/axi/pcie@1000120000/pci@0,0/rp1@0/ethernet@c040100000")
+            .unwrap()
+            .unwrap();
+        assert_eq!(eth.clock_rate("pclk"), Ok(Some(2_0000_0000)));
+        assert_eq!(eth.clock_rate("hclk"), Ok(Some(1_0000_0000)));
+        let error = eth.clock_rate("tsu_clk").unwrap_err();
+        assert_eq!(error.kind(), DtbErrorKind::UnsupportedClock);
+    }
+}
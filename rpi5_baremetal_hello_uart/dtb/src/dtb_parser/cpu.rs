@@ -0,0 +1,288 @@
+// CPU topology in /cpus and the PSCI firmware interface in /psci
+
+use super::*;
+
+/// how a secondary CPU is brought up ('enable-method')
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnableMethod<'a> {
+    /// CPU_ON of the firmware described by /psci
+    Psci,
+    /// the CPU waits in a loop until its entry point is written to `release_address`
+    SpinTable { release_address: u64 },
+    /// a vendor specific method (e.g. "brcm,bcm2836-smp")
+    Other(&'a str),
+}
+
+/// a cpu node of /cpus
+#[derive(Clone, Copy, Debug)]
+pub struct Cpu<'a> {
+    node: DtbNode<'a>,
+    mpidr: u64,
+    enable_method: Option<EnableMethod<'a>>,
+}
+
+impl<'a> Cpu<'a> {
+    pub fn node(&self) -> DtbNode<'a> {
+        self.node
+    }
+
+    /// affinity fields of MPIDR_EL1 ('reg'), compared with the register to find this CPU
+    pub fn mpidr(&self) -> u64 {
+        self.mpidr
+    }
+
+    /// None when the CPU has no 'enable-method', which is usual for the boot CPU
+    pub fn enable_method(&self) -> Option<EnableMethod<'a>> {
+        self.enable_method
+    }
+}
+
+/// iterator over the cpu nodes of /cpus, other children such as caches and cpu-map are skipped
+pub struct CpuIter<'a> {
+    // None when /cpus does not exist
+    children: Option<ChildIter<'a>>,
+    address_cells: u32,
+}
+
+impl<'a> CpuIter<'a> {
+    fn next_internal(&mut self) -> Result<Option<Cpu<'a>>, DtbError> {
+        let Some(children) = self.children.as_mut() else {
+            return Ok(None);
+        };
+        for node in children {
+            let node = node?;
+            if node
+                .property(DtbNode::PROP_DEVICE_TYPE)?
+                .and_then(|p| p.as_str())
+                != Some(DtbParser::DEVICE_TYPE_CPU)
+            {
+                continue;
+            }
+            let mpidr = node
+                .property(DtbNode::PROP_REG)?
+                .and_then(|reg| reg.as_cells()?.read(self.address_cells))
+                .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+            return Ok(Some(Cpu {
+                node,
+                mpidr: mpidr
+                    .try_into()
+                    .map_err(|_| node.error(DtbErrorKind::Overflow))?,
+                enable_method: Self::enable_method(node)?,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn enable_method(node: DtbNode<'a>) -> Result<Option<EnableMethod<'a>>, DtbError> {
+        let Some(method) = node.property(DtbParser::PROP_ENABLE_METHOD)? else {
+            return Ok(None);
+        };
+        let method = method
+            .as_str()
+            .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+        Ok(Some(match method {
+            DtbParser::ENABLE_METHOD_PSCI => EnableMethod::Psci,
+            DtbParser::ENABLE_METHOD_SPIN_TABLE => EnableMethod::SpinTable {
+                release_address: node
+                    .property(DtbParser::PROP_CPU_RELEASE_ADDR)?
+                    .ok_or_else(|| node.error(DtbErrorKind::NotFound))?
+                    .as_u64()
+                    .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?,
+            },
+            _ => EnableMethod::Other(method),
+        }))
+    }
+}
+
+impl<'a> Iterator for CpuIter<'a> {
+    type Item = Result<Cpu<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_internal();
+        if result.is_err() {
+            self.children = None;
+        }
+        result.transpose()
+    }
+}
+
+/// conduit of the PSCI calls ('method')
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PsciMethod {
+    Smc,
+    Hvc,
+}
+
+/// the /psci node
+#[derive(Clone, Copy, Debug)]
+pub struct Psci<'a> {
+    node: DtbNode<'a>,
+    method: PsciMethod,
+    // PSCI 0.2 and later define the function IDs, so the properties are only needed by 0.1
+    standard_ids: bool,
+}
+
+impl<'a> Psci<'a> {
+    const PROP_METHOD: &'static str = "method";
+    const PROP_CPU_SUSPEND: &'static str = "cpu_suspend";
+    const PROP_CPU_OFF: &'static str = "cpu_off";
+    const PROP_CPU_ON: &'static str = "cpu_on";
+    const PROP_MIGRATE: &'static str = "migrate";
+    const COMPATIBLE_PSCI_0_2: &'static str = "arm,psci-0.2";
+    // SMC64 function IDs of PSCI 0.2
+    const CPU_SUSPEND: u32 = 0xc400_0001;
+    const CPU_OFF: u32 = 0x8400_0002;
+    const CPU_ON: u32 = 0xc400_0003;
+    const MIGRATE: u32 = 0xc400_0005;
+
+    pub fn node(&self) -> DtbNode<'a> {
+        self.node
+    }
+
+    pub fn method(&self) -> PsciMethod {
+        self.method
+    }
+
+    pub fn cpu_suspend(&self) -> Result<Option<u32>, DtbError> {
+        self.function_id(Self::PROP_CPU_SUSPEND, Self::CPU_SUSPEND)
+    }
+
+    pub fn cpu_off(&self) -> Result<Option<u32>, DtbError> {
+        self.function_id(Self::PROP_CPU_OFF, Self::CPU_OFF)
+    }
+
+    pub fn cpu_on(&self) -> Result<Option<u32>, DtbError> {
+        self.function_id(Self::PROP_CPU_ON, Self::CPU_ON)
+    }
+
+    pub fn migrate(&self) -> Result<Option<u32>, DtbError> {
+        self.function_id(Self::PROP_MIGRATE, Self::MIGRATE)
+    }
+
+    // the property wins over the standard ID, as Linux does for firmware which needs it
+    fn function_id(&self, name: &str, standard: u32) -> Result<Option<u32>, DtbError> {
+        match self.node.property(name)? {
+            Some(id) => id
+                .as_u32()
+                .map(Some)
+                .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty)),
+            None => Ok(self.standard_ids.then_some(standard)),
+        }
+    }
+}
+
+impl DtbParser<'_> {
+    const NODE_CPUS: &'static str = "cpus";
+    const NODE_PSCI: &'static str = "psci";
+    const DEVICE_TYPE_CPU: &'static str = "cpu";
+    const PROP_ENABLE_METHOD: &'static str = "enable-method";
+    const PROP_CPU_RELEASE_ADDR: &'static str = "cpu-release-addr";
+    const ENABLE_METHOD_PSCI: &'static str = "psci";
+    const ENABLE_METHOD_SPIN_TABLE: &'static str = "spin-table";
+
+    /// iterates over the CPUs in /cpus
+    pub fn cpus(&self) -> Result<CpuIter<'_>, DtbError> {
+        let cpus = self.root()?.find_child(Self::NODE_CPUS)?;
+        Ok(CpuIter {
+            address_cells: match cpus {
+                Some(cpus) => cpus.address_cells()?,
+                None => 0,
+            },
+            children: cpus.map(|cpus| cpus.children()),
+        })
+    }
+
+    /// the PSCI firmware interface, None when /psci does not exist
+    pub fn psci(&self) -> Result<Option<Psci<'_>>, DtbError> {
+        let Some(node) = self.root()?.find_child(Self::NODE_PSCI)? else {
+            return Ok(None);
+        };
+        let method = match node
+            .property(Psci::PROP_METHOD)?
+            .ok_or_else(|| node.error(DtbErrorKind::NotFound))?
+            .as_str()
+        {
+            Some("smc") => PsciMethod::Smc,
+            Some("hvc") => PsciMethod::Hvc,
+            _ => return Err(node.error(DtbErrorKind::InvalidProperty)),
+        };
+        Ok(Some(Psci {
+            node,
+            method,
+            // "arm,psci-1.0" nodes also list "arm,psci-0.2"
+            standard_ids: node.is_compatible(Psci::COMPATIBLE_PSCI_0_2)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_cpus_and_psci() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let cpus: Vec<_> = parser.cpus().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            cpus.iter().map(|cpu| cpu.mpidr()).collect::<Vec<_>>(),
+            [0x000, 0x100, 0x200, 0x300]
+        );
+        assert!(
+            cpus.iter()
+                .all(|cpu| cpu.enable_method() == Some(EnableMethod::Psci))
+        );
+        assert_eq!(cpus[1].node().name().unwrap(), "cpu@1");
+
+        let psci = parser.psci().unwrap().unwrap();
+        assert_eq!(psci.method(), PsciMethod::Smc);
+        assert_eq!(psci.cpu_on().unwrap(), Some(0xc400_0003));
+        assert_eq!(psci.cpu_off().unwrap(), Some(0x8400_0002));
+
+        // a spin-table CPU and a PSCI 0.1 firmware with its own function IDs
+        let release_address = 0xd8u64.to_be_bytes();
+        let cpu_on = 0x9500_0001u32.to_be_bytes();
+        let edits = [
+            DtbEdit::SetProperty {
+                path: "/cpus/cpu@3",
+                name: "enable-method",
+                value: b"spin-table\0",
+            },
+            DtbEdit::SetProperty {
+                path: "/cpus/cpu@3",
+                name: "cpu-release-addr",
+                value: &release_address,
+            },
+            DtbEdit::SetProperty {
+                path: "/psci",
+                name: "compatible",
+                value: b"arm,psci\0",
+            },
+            DtbEdit::SetProperty {
+                path: "/psci",
+                name: "method",
+                value: b"hvc\0",
+            },
+            DtbEdit::SetProperty {
+                path: "/psci",
+                name: "cpu_on",
+                value: &cpu_on,
+            },
+        ];
+        let mut buf = vec![0u8; test_data.len() + 0x1000];
+        let blob = parser.write_modified(&mut buf, &edits).unwrap();
+        let parser = unsafe { DtbParser::init(blob.as_ptr() as usize) }.unwrap();
+        let cpu3 = parser.cpus().unwrap().nth(3).unwrap().unwrap();
+        assert_eq!(
+            cpu3.enable_method(),
+            Some(EnableMethod::SpinTable {
+                release_address: 0xd8
+            })
+        );
+        let psci = parser.psci().unwrap().unwrap();
+        assert_eq!(psci.method(), PsciMethod::Hvc);
+        assert_eq!(psci.cpu_on().unwrap(), Some(0x9500_0001));
+        assert_eq!(psci.cpu_off().unwrap(), None);
+    }
+}
@@ -0,0 +1,84 @@
+// header fields of the blob
+
+use super::*;
+
+/// decoded copy of the header
+///
+/// offsets are from the start of the blob
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DtbHeader {
+    pub total_size: u32,
+    pub off_dt_struct: u32,
+    pub off_dt_strings: u32,
+    pub off_mem_rsvmap: u32,
+    pub version: u32,
+    /// the oldest version this blob is compatible with
+    pub last_comp_version: u32,
+    /// physical id of the CPU which booted, same as 'reg' of its cpu node
+    pub boot_cpuid_phys: u32,
+    pub size_dt_strings: u32,
+    /// None before version 17
+    pub size_dt_struct: Option<u32>,
+}
+
+impl DtbParser<'_> {
+    pub fn header(&self) -> DtbHeader {
+        self.dtb_header.header()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFSET_VERSION: usize = 0x14;
+    const OFFSET_LAST_COMP_VERSION: usize = 0x18;
+    const OFFSET_SIZE_DT_STRUCT: usize = 0x24;
+
+    fn with_versions(blob: &[u8], version: u32, last_comp_version: u32) -> Vec<u8> {
+        let mut blob = blob.to_vec();
+        blob[OFFSET_VERSION..OFFSET_VERSION + 4].copy_from_slice(&version.to_be_bytes());
+        blob[OFFSET_LAST_COMP_VERSION..OFFSET_LAST_COMP_VERSION + 4]
+            .copy_from_slice(&last_comp_version.to_be_bytes());
+        if version < 17 {
+            // only padding before the memory reservation block in a version 16 header
+            blob[OFFSET_SIZE_DT_STRUCT..OFFSET_SIZE_DT_STRUCT + 4].fill(0);
+        }
+        blob
+    }
+
+    #[test]
+    fn read_header_versions() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+        let header = parser.header();
+        assert_eq!(header.total_size as usize, test_data.len());
+        assert_eq!((header.version, header.last_comp_version), (17, 16));
+        assert_eq!(header.boot_cpuid_phys, 0);
+        assert_eq!(
+            header.off_dt_strings + header.size_dt_strings,
+            header.total_size
+        );
+        assert!(header.size_dt_struct.is_some());
+
+        // older dtc and U-Boot write version 16 without size_dt_struct
+        let blob = with_versions(&test_data, 16, 16);
+        let parser = DtbParser::from_bytes(&blob).unwrap();
+        assert_eq!(parser.header().size_dt_struct, None);
+        let (stdout, _) = parser.find_stdout_node().unwrap().unwrap();
+        assert_eq!(stdout.name().unwrap(), "serial@7d001000");
+
+        // a newer blob is read through the compatible version
+        assert!(DtbParser::from_bytes(&with_versions(&test_data, 18, 17)).is_ok());
+        for (version, last_comp_version, offset) in
+            [(18, 18, OFFSET_LAST_COMP_VERSION), (15, 15, OFFSET_VERSION)]
+        {
+            let error =
+                DtbParser::from_bytes(&with_versions(&test_data, version, last_comp_version))
+                    .err()
+                    .unwrap();
+            assert_eq!(error.kind(), DtbErrorKind::UnsupportedVersion);
+            assert_eq!(error.offset(), Some(offset));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+// phandle resolution and interrupt tree walking
+
+use super::*;
+
+/// cells of an interrupt specifier, stored inline so that no allocator is needed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSpecifier {
+    cells: [u32; InterruptSpecifier::MAX_CELLS],
+    len: usize,
+}
+
+impl InterruptSpecifier {
+    // unit address (up to 3 cells for PCI) followed by the interrupt cells
+    const MAX_CELLS: usize = 8;
+
+    fn new() -> Self {
+        Self {
+            cells: [0; Self::MAX_CELLS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, cell: u32) -> Result<(), DtbError> {
+        if self.len == Self::MAX_CELLS {
+            return Err(DtbErrorKind::CapacityExceeded.into());
+        }
+        self.cells[self.len] = cell;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn cells(&self) -> &[u32] {
+        &self.cells[..self.len]
+    }
+}
+
+impl core::fmt::Debug for InterruptSpecifier {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.cells().fmt(f)
+    }
+}
+
+/// an interrupt resolved to the interrupt controller which finally receives it
+#[derive(Clone, Copy, Debug)]
+pub struct Interrupt<'a> {
+    controller: DtbNode<'a>,
+    specifier: InterruptSpecifier,
+}
+
+impl<'a> Interrupt<'a> {
+    /// the node which has the 'interrupt-controller' property
+    pub fn controller(&self) -> DtbNode<'a> {
+        self.controller
+    }
+
+    /// interrupt specifier decoded by the '#interrupt-cells' of the controller
+    pub fn specifier(&self) -> &[u32] {
+        self.specifier.cells()
+    }
+}
+
+impl DtbParser<'_> {
+    pub(super) const PROP_PHANDLE: &'static str = "phandle";
+    pub(super) const PROP_LINUX_PHANDLE: &'static str = "linux,phandle";
+
+    pub fn find_node_by_phandle(&self, phandle: u32) -> Result<Option<DtbNode<'_>>, DtbError> {
+        for token in StructTokenIter::new(self) {
+            let StructToken::BeginNode { address } = token? else {
+                continue;
+            };
+            let node = DtbNode::new(self, address);
+            if node.phandle()? == Some(phandle) {
+                return Ok(Some(node));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> DtbNode<'a> {
+    const PROP_INTERRUPTS: &'static str = "interrupts";
+    const PROP_INTERRUPTS_EXTENDED: &'static str = "interrupts-extended";
+    const PROP_INTERRUPT_PARENT: &'static str = "interrupt-parent";
+    const PROP_INTERRUPT_CELLS: &'static str = "#interrupt-cells";
+    const PROP_INTERRUPT_CONTROLLER: &'static str = "interrupt-controller";
+    const PROP_INTERRUPT_MAP: &'static str = "interrupt-map";
+    const PROP_INTERRUPT_MAP_MASK: &'static str = "interrupt-map-mask";
+
+    pub fn phandle(&self) -> Result<Option<u32>, DtbError> {
+        let property = match self.property(DtbParser::PROP_PHANDLE)? {
+            Some(property) => property,
+            None => match self.property(DtbParser::PROP_LINUX_PHANDLE)? {
+                Some(property) => property,
+                None => return Ok(None),
+            },
+        };
+        property
+            .as_u32()
+            .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))
+            .map(Some)
+    }
+
+    /// finds the interrupt parent in the same way as Linux
+    ///
+    /// 'interrupt-parent' is followed if present, otherwise the tree parent is used,
+    /// until a node which has '#interrupt-cells' is found
+    pub fn interrupt_parent(&self) -> Result<Option<DtbNode<'a>>, DtbError> {
+        let mut node = *self;
+        for _ in 0..Self::MAX_DEPTH {
+            let next = match node.property(Self::PROP_INTERRUPT_PARENT)? {
+                Some(phandle) => {
+                    let phandle = phandle
+                        .as_u32()
+                        .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+                    Some(
+                        self.parser
+                            .find_node_by_phandle(phandle)?
+                            .ok_or_else(|| node.error(DtbErrorKind::UnknownPhandle))?,
+                    )
+                }
+                None => node.parent()?,
+            };
+            let Some(next) = next else {
+                return Ok(None);
+            };
+            if next.property(Self::PROP_INTERRUPT_CELLS)?.is_some() {
+                return Ok(Some(next));
+            }
+            node = next;
+        }
+        Err(DtbErrorKind::TooDeep.into())
+    }
+
+    fn interrupt_cells(&self) -> Result<u32, DtbError> {
+        self.property(Self::PROP_INTERRUPT_CELLS)?
+            .ok_or_else(|| self.error(DtbErrorKind::NotFound))?
+            .as_u32()
+            .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))
+    }
+
+    /// iterates over 'interrupts-extended' or 'interrupts', each one is resolved to its controller
+    /// through 'interrupt-map' of the interrupt nexus nodes
+    pub fn interrupts(&self) -> Result<InterruptIter<'a>, DtbError> {
+        // unit address used to match 'interrupt-map'
+        let mut unit_address = InterruptSpecifier::new();
+        if let Some(reg) = self.property(Self::PROP_REG)? {
+            for cell in reg
+                .as_cells()
+                .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?
+            {
+                if unit_address.push(cell).is_err() {
+                    break;
+                }
+            }
+        }
+        let (cells, parent) = match self.property(Self::PROP_INTERRUPTS_EXTENDED)? {
+            Some(interrupts) => (interrupts, None),
+            None => match self.property(Self::PROP_INTERRUPTS)? {
+                Some(interrupts) => {
+                    // without a parent the cells would be taken as the phandles of
+                    // 'interrupts-extended'
+                    let parent = self
+                        .interrupt_parent()?
+                        .ok_or_else(|| self.error(DtbErrorKind::NotFound))?;
+                    (interrupts, Some(parent))
+                }
+                None => {
+                    return Ok(InterruptIter {
+                        node: *self,
+                        cells: None,
+                        parent: None,
+                        unit_address,
+                    });
+                }
+            },
+        };
+        let cells = cells
+            .as_cells()
+            .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?;
+        if let Some(parent) = parent
+            && !cells
+                .remaining()
+                .is_multiple_of(parent.interrupt_cells()? as usize)
+        {
+            return Err(self.error(DtbErrorKind::InvalidProperty));
+        }
+        Ok(InterruptIter {
+            node: *self,
+            cells: Some(cells),
+            parent,
+            unit_address,
+        })
+    }
+
+    // follows interrupt-map until an interrupt controller is reached
+    fn resolve_interrupt(
+        mut parent: DtbNode<'a>,
+        mut unit_address: InterruptSpecifier,
+        mut specifier: InterruptSpecifier,
+    ) -> Result<Interrupt<'a>, DtbError> {
+        for _ in 0..Self::MAX_DEPTH {
+            if parent.property(Self::PROP_INTERRUPT_CONTROLLER)?.is_some() {
+                return Ok(Interrupt {
+                    controller: parent,
+                    specifier,
+                });
+            }
+            let Some(map) = parent.property(Self::PROP_INTERRUPT_MAP)? else {
+                parent = parent
+                    .interrupt_parent()?
+                    .ok_or_else(|| parent.error(DtbErrorKind::NotFound))?;
+                continue;
+            };
+            let address_cells = parent.address_cells()? as usize;
+            let interrupt_cells = parent.interrupt_cells()? as usize;
+            if specifier.len != interrupt_cells {
+                return Err(parent.error(DtbErrorKind::InvalidProperty));
+            }
+            let mut key = InterruptSpecifier::new();
+            for i in 0..address_cells {
+                key.push(unit_address.cells().get(i).copied().unwrap_or(0))?;
+            }
+            for &cell in specifier.cells() {
+                key.push(cell)?;
+            }
+            if let Some(mask) = parent.property(Self::PROP_INTERRUPT_MAP_MASK)? {
+                let mask = mask
+                    .as_cells()
+                    .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?;
+                if mask.remaining() != key.len {
+                    return Err(parent.error(DtbErrorKind::InvalidProperty));
+                }
+                for (cell, mask) in key.cells.iter_mut().zip(mask) {
+                    *cell &= mask;
+                }
+            }
+
+            let mut map = map
+                .as_cells()
+                .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?;
+            let mut next = None;
+            while !map.is_empty() {
+                let mut matched = true;
+                for &cell in key.cells() {
+                    matched &= map
+                        .next()
+                        .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?
+                        == cell;
+                }
+                let phandle = map
+                    .next()
+                    .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?;
+                let new_parent = parent
+                    .parser
+                    .find_node_by_phandle(phandle)?
+                    .ok_or_else(|| parent.error(DtbErrorKind::UnknownPhandle))?;
+                // '#address-cells' of the parent in interrupt-map is 0 when it is not present
+                let new_address_cells = match new_parent.property(Self::PROP_ADDRESS_CELLS)? {
+                    Some(cells) => cells
+                        .as_u32()
+                        .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?,
+                    None => 0,
+                };
+                let mut new_address = InterruptSpecifier::new();
+                for _ in 0..new_address_cells {
+                    new_address.push(
+                        map.next()
+                            .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?,
+                    )?;
+                }
+                let mut new_specifier = InterruptSpecifier::new();
+                for _ in 0..new_parent.interrupt_cells()? {
+                    new_specifier.push(
+                        map.next()
+                            .ok_or_else(|| parent.error(DtbErrorKind::InvalidProperty))?,
+                    )?;
+                }
+                if matched {
+                    next = Some((new_parent, new_address, new_specifier));
+                    break;
+                }
+            }
+            (parent, unit_address, specifier) =
+                next.ok_or_else(|| parent.error(DtbErrorKind::UnmappedInterrupt))?;
+        }
+        Err(DtbErrorKind::TooDeep.into())
+    }
+}
+
+/// iterator over the interrupts of a node
+pub struct InterruptIter<'a> {
+    node: DtbNode<'a>,
+    cells: Option<Cells<'a>>,
+    // None when reading 'interrupts-extended'
+    parent: Option<DtbNode<'a>>,
+    unit_address: InterruptSpecifier,
+}
+
+impl<'a> InterruptIter<'a> {
+    fn next_internal(&mut self) -> Result<Option<Interrupt<'a>>, DtbError> {
+        let Some(cells) = self.cells.as_mut() else {
+            return Ok(None);
+        };
+        if cells.is_empty() {
+            return Ok(None);
+        }
+        let parent = match self.parent {
+            Some(parent) => parent,
+            None => {
+                let phandle = cells
+                    .next()
+                    .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty))?;
+                self.node
+                    .parser
+                    .find_node_by_phandle(phandle)?
+                    .ok_or_else(|| self.node.error(DtbErrorKind::UnknownPhandle))?
+            }
+        };
+        let mut specifier = InterruptSpecifier::new();
+        for _ in 0..parent.interrupt_cells()? {
+            specifier.push(
+                cells
+                    .next()
+                    .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty))?,
+            )?;
+        }
+        DtbNode::resolve_interrupt(parent, self.unit_address, specifier).map(Some)
+    }
+}
+
+impl<'a> Iterator for InterruptIter<'a> {
+    type Item = Result<Interrupt<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_internal();
+        if result.is_err() {
+            self.cells = None;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_interrupts() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let uart = parser.find_node_by_alias("serial10").unwrap().unwrap();
+        let gic = uart.interrupt_parent().unwrap().unwrap();
+        assert!(gic.is_compatible("arm,gic-400").unwrap());
+        assert_eq!(
+            parser
+                .find_node_by_phandle(gic.phandle().unwrap().unwrap())
+                .unwrap()
+                .unwrap()
+                .name()
+                .unwrap(),
+            gic.name().unwrap()
+        );
+        let interrupts: Vec<_> = uart.interrupts().unwrap().map(Result::unwrap).collect();
+        assert_eq!(interrupts.len(), 1);
+        assert_eq!(
+            interrupts[0].controller().name().unwrap(),
+            gic.name().unwrap()
+        );
+        assert_eq!(interrupts[0].specifier(), [0, 121, 4]);
+
+        // RP1 itself is wired to the GIC through interrupt-map of the PCIe root complex
+        let rp1_bridge = parser.find_node_by_path("pcie2/pci@0,0").unwrap().unwrap();
+        let interrupts: Vec<_> = rp1_bridge
+            .interrupts()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(interrupts.len(), 1);
+        assert!(
+            interrupts[0]
+                .controller()
+                .is_compatible("arm,gic-400")
+                .unwrap()
+        );
+        assert_eq!(interrupts[0].specifier(), [0, 229, 4]);
+
+        // devices on RP1 are handled by RP1's own interrupt controller
+        let rp1_uart = parser.find_node_by_alias("uart0").unwrap().unwrap();
+        let interrupt = rp1_uart.interrupts().unwrap().next().unwrap().unwrap();
+        assert_eq!(interrupt.controller().name().unwrap(), "rp1@0");
+        assert_eq!(interrupt.specifier(), [25, 4]);
+
+        let memory = parser.find_node_by_path("/memory").unwrap().unwrap();
+        assert!(memory.interrupts().unwrap().next().is_none());
+    }
+
+    #[test]
+    fn reject_invalid_interrupts() {
+        let mut buf = [0u8; 512];
+        let mut writer = FdtWriter::new(&mut buf).unwrap();
+        writer.begin_node("").unwrap();
+        // no interrupt parent, the first cell is not a phandle
+        writer.begin_node("orphan").unwrap();
+        writer.property("interrupts", &[0, 0, 0, 1]).unwrap();
+        writer.end_node().unwrap();
+        writer.begin_node("intc").unwrap();
+        writer.property("interrupt-controller", &[]).unwrap();
+        writer.property_u32("#interrupt-cells", 2).unwrap();
+        writer.property_u32("phandle", 1).unwrap();
+        writer.end_node().unwrap();
+        // not a multiple of the cells of the parent
+        writer.begin_node("short").unwrap();
+        writer.property_u32("interrupt-parent", 1).unwrap();
+        writer
+            .property("interrupts", &[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3])
+            .unwrap();
+        writer.end_node().unwrap();
+        // not a multiple of a cell
+        writer.begin_node("truncated").unwrap();
+        writer.property_u32("interrupt-parent", 1).unwrap();
+        writer.property("interrupts", &[0, 0, 0, 1, 0]).unwrap();
+        writer.end_node().unwrap();
+        writer.begin_node("valid").unwrap();
+        writer.property_u32("interrupt-parent", 1).unwrap();
+        writer
+            .property("interrupts", &[0, 0, 0, 1, 0, 0, 0, 2])
+            .unwrap();
+        writer.end_node().unwrap();
+        writer.end_node().unwrap();
+        let blob = writer.finish(0).unwrap();
+        let parser = unsafe { DtbParser::init(blob.as_ptr() as usize) }.unwrap();
+
+        let interrupts = |path| {
+            parser
+                .find_node_by_path(path)
+                .unwrap()
+                .unwrap()
+                .interrupts()
+                .err()
+                .map(|error| error.kind())
+        };
+        assert_eq!(interrupts("/orphan"), Some(DtbErrorKind::NotFound));
+        assert_eq!(interrupts("/short"), Some(DtbErrorKind::InvalidProperty));
+        assert_eq!(
+            interrupts("/truncated"),
+            Some(DtbErrorKind::InvalidProperty)
+        );
+        assert_eq!(interrupts("/valid"), None);
+    }
+}
@@ -0,0 +1,235 @@
+// physical memory map built from the memory nodes and the reservations
+
+use super::*;
+
+/// sorted list of non overlapping (address, size) pairs
+///
+/// the capacity is fixed so that this can be used before any allocator is ready
+#[derive(Clone)]
+pub struct MemoryMap<const N: usize> {
+    regions: [(usize, usize); N],
+    len: usize,
+}
+
+impl<const N: usize> MemoryMap<N> {
+    pub const fn new() -> Self {
+        Self {
+            regions: [(0, 0); N],
+            len: 0,
+        }
+    }
+
+    pub fn regions(&self) -> &[(usize, usize)] {
+        &self.regions[..self.len]
+    }
+
+    /// adds a region, overlapping or adjacent regions are merged
+    pub fn add(&mut self, address: usize, size: usize) -> Result<(), DtbError> {
+        if size == 0 {
+            return Ok(());
+        }
+        let mut start = address;
+        let mut end = address.checked_add(size).ok_or(DtbErrorKind::Overflow)?;
+        // first region which ends at or after the new one starts
+        let first = self.regions().partition_point(|&(a, s)| a + s < start);
+        let mut last = first;
+        while last < self.len && self.regions[last].0 <= end {
+            start = start.min(self.regions[last].0);
+            end = end.max(self.regions[last].0 + self.regions[last].1);
+            last += 1;
+        }
+        if first == last {
+            self.insert(first, (start, end - start))?;
+        } else {
+            self.regions[first] = (start, end - start);
+            for _ in first + 1..last {
+                self.remove_at(first + 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// removes a range, a region which contains the whole range is split into two
+    pub fn remove(&mut self, address: usize, size: usize) -> Result<(), DtbError> {
+        if size == 0 {
+            return Ok(());
+        }
+        let start = address;
+        let end = address.saturating_add(size);
+        let mut i = 0;
+        while i < self.len {
+            let (region_start, region_size) = self.regions[i];
+            let region_end = region_start + region_size;
+            if region_end <= start || end <= region_start {
+                i += 1;
+                continue;
+            }
+            let left = (region_start < start).then(|| (region_start, start - region_start));
+            let right = (end < region_end).then(|| (end, region_end - end));
+            match (left, right) {
+                (Some(left), Some(right)) => {
+                    self.insert(i + 1, right)?;
+                    self.regions[i] = left;
+                    i += 2;
+                }
+                (Some(region), None) | (None, Some(region)) => {
+                    self.regions[i] = region;
+                    i += 1;
+                }
+                (None, None) => self.remove_at(i),
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, index: usize, region: (usize, usize)) -> Result<(), DtbError> {
+        if self.len == N {
+            return Err(DtbErrorKind::CapacityExceeded.into());
+        }
+        self.regions.copy_within(index..self.len, index + 1);
+        self.regions[index] = region;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove_at(&mut self, index: usize) {
+        self.regions.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+    }
+}
+
+impl<const N: usize> Default for MemoryMap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for MemoryMap<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.regions()
+                    .iter()
+                    .map(|&(address, size)| address..address + size),
+            )
+            .finish()
+    }
+}
+
+impl DtbParser<'_> {
+    const DEVICE_TYPE_MEMORY: &'static str = "memory";
+
+    /// collects the usable RAM
+    ///
+    /// every 'reg' entry of every memory node is merged, then the memory reservation block,
+    /// the statically placed /reserved-memory regions (including reusable ones), the dtb itself
+    /// and `excluded` (e.g. the kernel image) are removed
+    pub fn usable_memory<const N: usize>(
+        &self,
+        excluded: &[(usize, usize)],
+    ) -> Result<MemoryMap<N>, DtbError> {
+        let mut map = MemoryMap::new();
+        for token in StructTokenIter::new(self) {
+            let StructToken::BeginNode { address } = token? else {
+                continue;
+            };
+            let node = DtbNode::new(self, address);
+            if node
+                .property(DtbNode::PROP_DEVICE_TYPE)?
+                .and_then(|p| p.as_str())
+                != Some(Self::DEVICE_TYPE_MEMORY)
+            {
+                continue;
+            }
+            for reg in node.reg()? {
+                let (address, size) = reg?;
+                map.add(address, size)?;
+            }
+        }
+
+        for (address, size) in self.memory_reservations() {
+            map.remove(address, size)?;
+        }
+        for reserved in self.reserved_memory()? {
+            for reg in reserved?.reg()? {
+                let (address, size) = reg?;
+                map.remove(address, size)?;
+            }
+        }
+        let (dtb_address, dtb_size) = self.dtb_region();
+        map.remove(dtb_address, dtb_size)?;
+        for &(address, size) in excluded {
+            map.remove(address, size)?;
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_and_subtract_regions() {
+        let mut map = MemoryMap::<4>::new();
+        // banks are not sorted and the first one is reported twice
+        map.add(0x1_0000_0000, 0x1_0000_0000).unwrap();
+        map.add(0x0, 0x4000_0000).unwrap();
+        map.add(0x2_0000_0000, 0x1_0000_0000).unwrap();
+        map.add(0x0, 0x2800_0000).unwrap();
+        assert_eq!(
+            map.regions(),
+            [(0x0, 0x4000_0000), (0x1_0000_0000, 0x2_0000_0000)]
+        );
+
+        map.remove(0x1000, 0x1000).unwrap();
+        map.remove(0x3000_0000, 0x1_0000_0000).unwrap();
+        map.remove(0x2_8000_0000, 0x1000_0000).unwrap();
+        assert_eq!(
+            map.regions(),
+            [
+                (0x0, 0x1000),
+                (0x2000, 0x2fff_e000),
+                (0x1_3000_0000, 0x1_5000_0000),
+                (0x2_9000_0000, 0x7000_0000),
+            ]
+        );
+        assert!(map.remove(0x1_4000_0000, 0x1000).is_err());
+        map.remove(0x0, usize::MAX).unwrap();
+        assert!(map.regions().is_empty());
+    }
+
+    #[test]
+    fn usable_memory_from_dtb() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        // kernel image loaded at 0x200000 with its stack up to 0x4000000
+        let map = parser
+            .usable_memory::<8>(&[(0x20_0000, 0x3e0_0000)])
+            .unwrap();
+        assert_eq!(
+            map.regions(),
+            [(0x8_0000, 0x18_0000), (0x400_0000, 0x2400_0000)]
+        );
+
+        // several memory nodes with multiple entries, and banks above 4GiB
+        let test_data = std::fs::read("test/memory.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+        let map = parser
+            .usable_memory::<8>(&[(0x20_0000, 0x20_0000)])
+            .unwrap();
+        assert_eq!(
+            map.regions(),
+            [
+                // the banks below 1GiB are merged, then split by /memreserve/ and `excluded`
+                (0x1000, 0x1f_f000),
+                (0x40_0000, 0x3fc0_0000),
+                // the overlapping entries are merged, then split by /reserved-memory
+                (0x1_0000_0000, 0x4000_0000),
+                (0x1_4010_0000, 0x3ff0_0000),
+                (0x2_0000_0000, 0x4000_0000),
+            ]
+        );
+    }
+}
@@ -0,0 +1,743 @@
+// node handle which reads the properties directly from the flattened device tree
+
+use super::*;
+
+/// handle to a node in the structure block
+///
+/// this only holds the address of the node, so it is cheap to copy and does not need an allocator.
+/// properties are read from the blob every time they are requested
+#[derive(Clone, Copy)]
+pub struct DtbNode<'a> {
+    pub(super) parser: &'a DtbParser<'a>,
+    // points to the FDT_BEGIN_NODE token
+    pub(super) address: usize,
+}
+
+// addresses of the ancestors of a node, ordered from the root node
+struct Ancestors {
+    addresses: [usize; DtbNode::MAX_DEPTH],
+    depth: usize,
+}
+
+impl<'a> DtbNode<'a> {
+    pub(super) const MAX_DEPTH: usize = 32;
+    pub(super) const PROP_ADDRESS_CELLS: &'static str = "#address-cells";
+    const PROP_SIZE_CELLS: &'static str = "#size-cells";
+    const PROP_COMPATIBLE: &'static str = "compatible";
+    const PROP_STATUS: &'static str = "status";
+    pub(super) const PROP_REG: &'static str = "reg";
+    const PROP_RANGES: &'static str = "ranges";
+    const PROP_DMA_RANGES: &'static str = "dma-ranges";
+    pub(super) const PROP_DEVICE_TYPE: &'static str = "device_type";
+
+    pub(crate) fn new(parser: &'a DtbParser<'a>, address: usize) -> Self {
+        Self { parser, address }
+    }
+
+    pub fn name(&self) -> Result<&'a str, DtbError> {
+        self.parser.dtb_header.read_char_str(
+            self.address + DtbParser::SIZEOF_FDT_TOKEN,
+            self.parser.dtb_header.get_struct_end_address(),
+        )
+    }
+
+    pub fn properties(&self) -> PropertyIter<'a> {
+        PropertyIter {
+            tokens: StructTokenIter::new_at(self.parser, self.address),
+            in_node: false,
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Result<Option<DtbProperty<'a>>, DtbError> {
+        for property in self.properties() {
+            let property = property?;
+            if property.name() == name {
+                return Ok(Some(property));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn children(&self) -> ChildIter<'a> {
+        ChildIter {
+            tokens: StructTokenIter::new_at(self.parser, self.address),
+            depth: 0,
+        }
+    }
+
+    /// finds a direct child by name, the unit address is optional
+    ///
+    /// an exact match is preferred over a match without the unit address
+    pub fn find_child(&self, name: &str) -> Result<Option<DtbNode<'a>>, DtbError> {
+        let mut candidate = None;
+        for child in self.children() {
+            let child = child?;
+            let child_name = child.name()?;
+            if child_name == name {
+                return Ok(Some(child));
+            }
+            if candidate.is_none()
+                && !name.contains('@')
+                && child_name.split('@').next() == Some(name)
+            {
+                candidate = Some(child);
+            }
+        }
+        Ok(candidate)
+    }
+
+    /// returns None for the root node
+    ///
+    /// the parent is searched from the root node, so this takes time proportional to the size of the tree
+    pub fn parent(&self) -> Result<Option<DtbNode<'a>>, DtbError> {
+        let ancestors = self.ancestors()?;
+        Ok(ancestors
+            .depth
+            .checked_sub(1)
+            .map(|i| DtbNode::new(self.parser, ancestors.addresses[i])))
+    }
+
+    fn ancestors(&self) -> Result<Ancestors, DtbError> {
+        let mut ancestors = Ancestors {
+            addresses: [0; Self::MAX_DEPTH],
+            depth: 0,
+        };
+        for token in StructTokenIter::new(self.parser) {
+            match token? {
+                StructToken::BeginNode { address, .. } => {
+                    if address == self.address {
+                        return Ok(ancestors);
+                    }
+                    if ancestors.depth == Self::MAX_DEPTH {
+                        return Err(DtbErrorKind::TooDeep.into());
+                    }
+                    ancestors.addresses[ancestors.depth] = address;
+                    ancestors.depth += 1;
+                }
+                StructToken::EndNode => {
+                    ancestors.depth = ancestors
+                        .depth
+                        .checked_sub(1)
+                        .ok_or(DtbErrorKind::UnbalancedNode)?;
+                }
+                StructToken::Property { .. } => {}
+            }
+        }
+        Err(DtbErrorKind::InvalidArgument.into())
+    }
+
+    // error located at this node, the path is left out when the tree above it is broken
+    pub(crate) fn error(&self, kind: DtbErrorKind) -> DtbError {
+        let error = self.parser.error_at(kind, self.address);
+        let Ok(ancestors) = self.ancestors() else {
+            return error;
+        };
+        let names = core::iter::once(self.address)
+            .chain(ancestors.addresses[..ancestors.depth].iter().rev().copied())
+            // the root node has no name
+            .take(ancestors.depth)
+            .map(|address| DtbNode::new(self.parser, address).name().unwrap_or("?"));
+        error.with_path(names)
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> Result<bool, DtbError> {
+        if let Some(property) = self.property(Self::PROP_COMPATIBLE)? {
+            for name in property.as_str_list() {
+                if name? == compatible {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// returns the index of the first entry of `compatibles` listed in 'compatible'
+    ///
+    /// `compatibles` is ordered by preference, so a node listing several of them matches the
+    /// earliest one regardless of the order in the node
+    pub fn match_compatible(&self, compatibles: &[&str]) -> Result<Option<usize>, DtbError> {
+        let Some(property) = self.property(Self::PROP_COMPATIBLE)? else {
+            return Ok(None);
+        };
+        let mut matched: Option<usize> = None;
+        for name in property.as_str_list() {
+            let name = name?;
+            if let Some(index) = compatibles.iter().position(|c| *c == name) {
+                matched = Some(matched.map_or(index, |matched| matched.min(index)));
+            }
+        }
+        Ok(matched)
+    }
+
+    /// false when 'status' is present and is not "okay"
+    pub fn is_enabled(&self) -> Result<bool, DtbError> {
+        Ok(match self.property(Self::PROP_STATUS)? {
+            // "ok" is found in old device trees
+            Some(status) => matches!(status.as_str(), Some("okay" | "ok")),
+            None => true,
+        })
+    }
+
+    /// "#address-cells" of this node, 2 when it is not present
+    pub fn address_cells(&self) -> Result<u32, DtbError> {
+        Ok(self
+            .property(Self::PROP_ADDRESS_CELLS)?
+            .map(|p| {
+                p.as_u32()
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))
+            })
+            .transpose()?
+            .unwrap_or(2))
+    }
+
+    /// "#size-cells" of this node, 1 when it is not present
+    pub fn size_cells(&self) -> Result<u32, DtbError> {
+        Ok(self
+            .property(Self::PROP_SIZE_CELLS)?
+            .map(|p| {
+                p.as_u32()
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))
+            })
+            .transpose()?
+            .unwrap_or(1))
+    }
+
+    /// iterates over the 'reg' entries translated into the CPU physical address space
+    pub fn reg(&self) -> Result<RegIter<'a>, DtbError> {
+        let ancestors = self.ancestors()?;
+        let parent = ancestors
+            .depth
+            .checked_sub(1)
+            .map(|i| DtbNode::new(self.parser, ancestors.addresses[i]))
+            .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?;
+        let address_cells = parent.address_cells()?;
+        let size_cells = parent.size_cells()?;
+        let cells = match self.property(Self::PROP_REG)? {
+            Some(reg) => Some(
+                reg.as_cells()
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?,
+            ),
+            None => None,
+        };
+        Ok(RegIter {
+            node: *self,
+            ancestors,
+            cells,
+            address_cells,
+            size_cells,
+        })
+    }
+
+    /// translates an address on the bus below this node into the CPU physical address space
+    ///
+    /// the address is encoded like the 'reg' of the children, e.g. 3 cells on a PCI bus
+    pub fn translate_address(&self, address: u128, size: u128) -> Result<usize, DtbError> {
+        let mut ancestors = self.ancestors()?;
+        if ancestors.depth == Self::MAX_DEPTH {
+            return Err(self.error(DtbErrorKind::TooDeep));
+        }
+        ancestors.addresses[ancestors.depth] = self.address;
+        ancestors.depth += 1;
+        Self::translate(self.parser, &ancestors, address, size)?
+            .try_into()
+            .map_err(|_| self.error(DtbErrorKind::Overflow))
+    }
+
+    /// converts a CPU physical address into the address this device puts on its bus for DMA
+    ///
+    /// the address goes down from the root node through the 'dma-ranges' of every bus above the
+    /// device, a bus without 'dma-ranges' is treated as a 1:1 mapping
+    pub fn dma_address(&self, cpu_address: usize, size: usize) -> Result<usize, DtbError> {
+        let ancestors = self.ancestors()?;
+        let mut address = cpu_address as u128;
+        for i in 1..ancestors.depth {
+            let bus = DtbNode::new(self.parser, ancestors.addresses[i]);
+            let parent = DtbNode::new(self.parser, ancestors.addresses[i - 1]);
+            address = bus.map_range(parent, Self::PROP_DMA_RANGES, address, size as u128, false)?;
+        }
+        if let Some(i) = ancestors.depth.checked_sub(1)
+            && DtbNode::new(self.parser, ancestors.addresses[i]).is_pci_bus()?
+        {
+            // a PCI device only puts the 64 bit address on the bus
+            address &= u128::from(u64::MAX);
+        }
+        pr_debug!(
+            "dma: cpu address: {:#x}, bus address: {:#x}",
+            cpu_address,
+            address
+        );
+        address
+            .try_into()
+            .map_err(|_| self.error(DtbErrorKind::Overflow))
+    }
+
+    // translates a bus address of a child of ancestors[depth - 1] through every parent 'ranges'
+    fn translate(
+        parser: &DtbParser<'_>,
+        ancestors: &Ancestors,
+        mut address: u128,
+        size: u128,
+    ) -> Result<u128, DtbError> {
+        for i in (1..ancestors.depth).rev() {
+            let bus = DtbNode::new(parser, ancestors.addresses[i]);
+            let parent = DtbNode::new(parser, ancestors.addresses[i - 1]);
+            address = bus.map_range(parent, Self::PROP_RANGES, address, size, true)?;
+        }
+        Ok(address)
+    }
+
+    // maps an address across this bus with the entries of 'ranges' or 'dma-ranges'
+    //
+    // `to_parent` maps a child address to the parent address space, otherwise the other way round.
+    // a missing or empty property is an identity mapping
+    fn map_range(
+        &self,
+        parent: DtbNode<'a>,
+        name: &str,
+        address: u128,
+        size: u128,
+        to_parent: bool,
+    ) -> Result<u128, DtbError> {
+        let Some(ranges) = self.property(name)? else {
+            return Ok(address);
+        };
+        if ranges.is_empty() {
+            return Ok(address);
+        }
+        let child_address_cells = self.address_cells()?;
+        let parent_address_cells = parent.address_cells()?;
+        let size_cells = self.size_cells()?;
+        let pci = if to_parent {
+            self.is_pci_bus()?
+        } else {
+            parent.is_pci_bus()?
+        };
+        let (space, offset) = Self::split_pci_address(address, pci);
+        let invalid_ranges = || self.error(DtbErrorKind::InvalidProperty);
+        let mut cells = ranges.as_cells().ok_or_else(invalid_ranges)?;
+        while !cells.is_empty() {
+            let child = cells.read(child_address_cells).ok_or_else(invalid_ranges)?;
+            let parent = cells
+                .read(parent_address_cells)
+                .ok_or_else(invalid_ranges)?;
+            let len = cells.read(size_cells).ok_or_else(invalid_ranges)?;
+            let (from, to) = if to_parent {
+                (child, parent)
+            } else {
+                (parent, child)
+            };
+            let (from_space, from_offset) = Self::split_pci_address(from, pci);
+            let in_range = from_space == space
+                && from_offset <= offset
+                && offset
+                    .checked_add(size)
+                    .zip(from_offset.checked_add(len))
+                    .is_some_and(|(end, from_end)| end <= from_end);
+            if in_range {
+                return to
+                    .checked_add(offset - from_offset)
+                    .ok_or_else(|| self.error(DtbErrorKind::Overflow));
+            }
+        }
+        Err(self.error(DtbErrorKind::UntranslatableAddress))
+    }
+
+    // whether the children of this node are on a PCI bus
+    fn is_pci_bus(&self) -> Result<bool, DtbError> {
+        Ok(matches!(
+            self.property(Self::PROP_DEVICE_TYPE)?
+                .and_then(|p| p.as_str()),
+            Some("pci" | "pciex")
+        ))
+    }
+
+    // splits an address into the PCI space code and the address in the space
+    //
+    // the other bits of phys.hi (e.g. prefetchable) differ between 'reg' and 'ranges', and
+    // 64 bit memory is looked up in the same space as 32 bit memory
+    fn split_pci_address(address: u128, pci: bool) -> (u32, u128) {
+        if !pci {
+            return (0, address);
+        }
+        let space = match (address >> 88) as u32 & 0b11 {
+            0b11 => 0b10,
+            space => space,
+        };
+        (space, address & u128::from(u64::MAX))
+    }
+}
+
+impl core::fmt::Debug for DtbNode<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DtbNode")
+            .field("name", &self.name())
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+/// a property of a node
+#[derive(Clone, Copy, Debug)]
+pub struct DtbProperty<'a> {
+    name: &'a str,
+    value: &'a [u8],
+}
+
+impl<'a> DtbProperty<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// raw value of the property
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.value.try_into().ok()?))
+    }
+
+    /// reads a value encoded in one or two cells
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => self.as_u32().map(u64::from),
+            8 => Some(u64::from_be_bytes(self.value.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// reads a single null terminated string
+    pub fn as_str(&self) -> Option<&'a str> {
+        let (last, body) = self.value.split_last()?;
+        if *last != 0 || body.contains(&0) {
+            return None;
+        }
+        core::str::from_utf8(body).ok()
+    }
+
+    pub fn as_str_list(&self) -> StrListIter<'a> {
+        StrListIter { value: self.value }
+    }
+
+    /// returns None when the length is not a multiple of the cell size
+    pub fn as_cells(&self) -> Option<Cells<'a>> {
+        if !self.value.len().is_multiple_of(size_of::<u32>()) {
+            return None;
+        }
+        Some(Cells { value: self.value })
+    }
+}
+
+/// iterator over a list of null terminated strings
+#[derive(Clone)]
+pub struct StrListIter<'a> {
+    value: &'a [u8],
+}
+
+impl<'a> Iterator for StrListIter<'a> {
+    type Item = Result<&'a str, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.value.is_empty() {
+            return None;
+        }
+        let Some(end) = self.value.iter().position(|&c| c == 0) else {
+            self.value = &[];
+            return Some(Err(DtbErrorKind::InvalidString.into()));
+        };
+        let s = &self.value[..end];
+        self.value = &self.value[end + 1..];
+        Some(core::str::from_utf8(s).map_err(|_| DtbErrorKind::InvalidString.into()))
+    }
+}
+
+/// iterator over big endian u32 cells
+#[derive(Clone)]
+pub struct Cells<'a> {
+    value: &'a [u8],
+}
+
+impl<'a> Cells<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    /// number of the remaining cells
+    pub fn remaining(&self) -> usize {
+        self.value.len() / size_of::<u32>()
+    }
+
+    /// reads a number encoded in `cells` cells, up to 4 cells are supported
+    pub fn read(&mut self, cells: u32) -> Option<u128> {
+        if cells > 4 || self.remaining() < cells as usize {
+            return None;
+        }
+        let mut result = 0;
+        for _ in 0..cells {
+            result = (result << 32) | u128::from(self.next()?);
+        }
+        Some(result)
+    }
+}
+
+impl<'a> Iterator for Cells<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (cell, rest) = self.value.split_first_chunk::<4>()?;
+        self.value = rest;
+        Some(u32::from_be_bytes(*cell))
+    }
+}
+
+pub struct PropertyIter<'a> {
+    tokens: StructTokenIter<'a>,
+    in_node: bool,
+}
+
+impl<'a> Iterator for PropertyIter<'a> {
+    type Item = Result<DtbProperty<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.tokens.next()? {
+                Ok(StructToken::BeginNode { .. }) if !self.in_node => self.in_node = true,
+                Ok(StructToken::Property { name, value }) if self.in_node => {
+                    return Some(Ok(DtbProperty { name, value }));
+                }
+                // properties end at the first child node or the end of this node
+                Ok(_) => {
+                    self.tokens.finished = true;
+                    return None;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+pub struct ChildIter<'a> {
+    tokens: StructTokenIter<'a>,
+    depth: usize,
+}
+
+impl<'a> Iterator for ChildIter<'a> {
+    type Item = Result<DtbNode<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.tokens.next()? {
+                Ok(StructToken::BeginNode { address, .. }) => {
+                    self.depth += 1;
+                    if self.depth == 2 {
+                        return Some(Ok(DtbNode::new(self.tokens.parser, address)));
+                    }
+                }
+                Ok(StructToken::EndNode) => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        self.tokens.finished = true;
+                        return None;
+                    }
+                }
+                Ok(StructToken::Property { .. }) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// iterator over the translated (address, size) pairs of the 'reg' property
+pub struct RegIter<'a> {
+    node: DtbNode<'a>,
+    ancestors: Ancestors,
+    cells: Option<Cells<'a>>,
+    address_cells: u32,
+    size_cells: u32,
+}
+
+impl<'a> RegIter<'a> {
+    fn next_internal(&mut self) -> Result<Option<(usize, usize)>, DtbError> {
+        let Some(cells) = self.cells.as_mut().filter(|cells| !cells.is_empty()) else {
+            return Ok(None);
+        };
+        let node = self.node;
+        let address = cells
+            .read(self.address_cells)
+            .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+        let size = cells
+            .read(self.size_cells)
+            .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+        let address = DtbNode::translate(node.parser, &self.ancestors, address, size)?;
+        pr_debug!("reg: address: {:#x}, size: {:#x}", address, size);
+        Ok(Some((
+            address
+                .try_into()
+                .map_err(|_| node.error(DtbErrorKind::Overflow))?,
+            size.try_into()
+                .map_err(|_| node.error(DtbErrorKind::Overflow))?,
+        )))
+    }
+}
+
+impl<'a> Iterator for RegIter<'a> {
+    type Item = Result<(usize, usize), DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_internal();
+        if result.is_err() {
+            self.cells = None;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_node_properties() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let mut counter = 0;
+        parser
+            .find_nodes(None, Some("arm,pl011"), &mut |node| {
+                assert_eq!(node.name().unwrap(), "serial@7d001000");
+                let mut reg = node.reg().unwrap();
+                assert_eq!(reg.next().unwrap().unwrap(), (0x10_7D00_1000, 0x200));
+                assert!(reg.next().is_none());
+                let compatible = node.property("compatible").unwrap().unwrap();
+                assert_eq!(
+                    compatible
+                        .as_str_list()
+                        .collect::<Result<Vec<_>, _>>()
+                        .unwrap(),
+                    ["arm,pl011", "arm,primecell"]
+                );
+                assert_eq!(compatible.as_str(), None);
+                let interrupts = node.property("interrupts").unwrap().unwrap();
+                assert_eq!(
+                    interrupts.as_cells().unwrap().collect::<Vec<_>>(),
+                    [0, 121, 4]
+                );
+                assert_eq!(
+                    node.property("arm,primecell-periphid")
+                        .unwrap()
+                        .unwrap()
+                        .as_u32(),
+                    Some(0x0024_1011)
+                );
+                assert_eq!(
+                    node.property("status").unwrap().unwrap().as_str(),
+                    Some("okay")
+                );
+                assert!(node.property("clock-frequency").unwrap().is_none());
+                let parent = node.parent().unwrap().unwrap();
+                assert_eq!(parent.name().unwrap(), "soc@107c000000");
+                assert_eq!(parent.address_cells().unwrap(), 1);
+                assert!(
+                    parent
+                        .children()
+                        .any(|child| child.unwrap().address == node.address)
+                );
+                counter += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 1);
+
+        // RP1 is located behind the PCIe root complex
+        counter = 0;
+        parser
+            .find_nodes(None, Some("arm,pl011-axi"), &mut |node| {
+                assert_eq!(node.name().unwrap(), "serial@c040030000");
+                assert_eq!(
+                    node.reg().unwrap().next().unwrap().unwrap(),
+                    (0x1f_0003_0000, 0x100)
+                );
+                counter += 1;
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(counter, 1);
+
+        let root = parser.root().unwrap();
+        assert_eq!(root.name().unwrap(), "");
+        assert!(root.parent().unwrap().is_none());
+        assert!(root.reg().is_err());
+        let clock = root
+            .children()
+            .map(Result::unwrap)
+            .find(|node| node.name().unwrap() == "clocks")
+            .unwrap()
+            .children()
+            .map(Result::unwrap)
+            .find(|node| node.name().unwrap() == "clk-uart")
+            .unwrap();
+        assert_eq!(
+            clock.property("clock-frequency").unwrap().unwrap().as_u64(),
+            Some(4400_0000)
+        );
+
+        counter = 0;
+        parser
+            .find_nodes(None, Some("arm,gic-400"), &mut |node| {
+                counter += node.reg().unwrap().count();
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 4);
+    }
+
+    #[test]
+    fn translate_bus_addresses() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+        let node = |path| parser.find_node_by_path(path).unwrap().unwrap();
+
+        // every window of 'ranges' is searched, the prefetchable 64 bit window is the second one
+        let pcie2 = node("/axi/pcie@1000120000");
+        assert_eq!(
+            pcie2.translate_address(0x0200_0000_0000_0000_0000_1000, 0x1000),
+            Ok(0x1f_0000_1000)
+        );
+        assert_eq!(
+            pcie2.translate_address(0x4300_0000_0000_0004_0000_1000, 0x1000),
+            Ok(0x1c_0000_1000)
+        );
+        // only the space code of phys.hi is compared
+        assert_eq!(
+            pcie2.translate_address(0x8300_0000_0000_0004_0000_1000, 0x1000),
+            Ok(0x1c_0000_1000)
+        );
+        assert_eq!(
+            pcie2
+                .translate_address(0x0100_0000_0000_0000_0000_1000, 0x1000)
+                .map_err(|e| e.kind()),
+            Err(DtbErrorKind::UntranslatableAddress)
+        );
+        // the end of the region has to be in the window too
+        assert!(
+            node("/axi/pcie@1000120000/pci@0,0/rp1@0")
+                .translate_address(0xc0_403f_f000, 0x2000)
+                .is_err()
+        );
+
+        // RP1 sees the memory of the CPU at 0x10_0000_0000 through the PCIe root complex
+        let uart0 = node("/axi/pcie@1000120000/pci@0,0/rp1@0/serial@c040030000");
+        assert_eq!(uart0.dma_address(0x1000, 0x100), Ok(0x10_0000_1000));
+        let uart10 = node("/soc/serial@7d001000");
+        assert_eq!(uart10.dma_address(0x1000, 0x100), Ok(0x8000_1000));
+        let error = uart10.dma_address(0x8000_0000, 0x100).unwrap_err();
+        assert_eq!(error.kind(), DtbErrorKind::UntranslatableAddress);
+        // no 'dma-ranges' above the node is a 1:1 mapping
+        let memory = node("/memory@0");
+        assert_eq!(memory.dma_address(0x1000, 0x100), Ok(0x1000));
+    }
+}
@@ -0,0 +1,545 @@
+// device tree overlay (.dtbo) application
+
+use super::*;
+
+// fragments of an overlay and the base nodes they target
+struct Fragments<'o> {
+    // (address of the target node in the base tree, __overlay__ node)
+    fragments: [Option<(usize, DtbNode<'o>)>; Fragments::MAX_FRAGMENTS],
+    len: usize,
+}
+
+// overlay nodes merged into the same node, in the order of the fragments
+#[derive(Clone, Copy)]
+struct OverlayNodes<'o> {
+    nodes: [Option<DtbNode<'o>>; Fragments::MAX_FRAGMENTS],
+    len: usize,
+}
+
+impl<'o> OverlayNodes<'o> {
+    fn new() -> Self {
+        Self {
+            nodes: [None; Fragments::MAX_FRAGMENTS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, node: DtbNode<'o>) {
+        self.nodes[self.len] = Some(node);
+        self.len += 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = DtbNode<'o>> + '_ {
+        self.nodes[..self.len].iter().flatten().copied()
+    }
+
+    // the value given by the last fragment wins
+    fn property(&self, name: &str) -> Result<Option<DtbProperty<'o>>, DtbError> {
+        let mut result = None;
+        for node in self.iter() {
+            if let Some(property) = node.property(name)? {
+                result = Some(property);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<'o> Fragments<'o> {
+    const MAX_FRAGMENTS: usize = 32;
+    const NODE_OVERLAY: &'static str = "__overlay__";
+    const PROP_TARGET: &'static str = "target";
+    const PROP_TARGET_PATH: &'static str = "target-path";
+
+    fn new(base: &DtbParser<'_>, overlay: &'o DtbParser<'o>) -> Result<Self, DtbError> {
+        let mut fragments = Self {
+            fragments: [None; Self::MAX_FRAGMENTS],
+            len: 0,
+        };
+        for fragment in overlay.root()?.children() {
+            let fragment = fragment?;
+            let Some(content) = child_by_name(fragment, Self::NODE_OVERLAY)? else {
+                continue;
+            };
+            let target = if let Some(target) = fragment.property(Self::PROP_TARGET)? {
+                let phandle = target
+                    .as_u32()
+                    .ok_or_else(|| fragment.error(DtbErrorKind::InvalidOverlay))?;
+                base.find_node_by_phandle(phandle)?
+            } else if let Some(path) = fragment.property(Self::PROP_TARGET_PATH)? {
+                base.find_node_by_path(
+                    path.as_str()
+                        .ok_or_else(|| fragment.error(DtbErrorKind::InvalidOverlay))?,
+                )?
+            } else {
+                return Err(fragment.error(DtbErrorKind::InvalidOverlay));
+            };
+            let target = target.ok_or_else(|| fragment.error(DtbErrorKind::NotFound))?;
+            if fragments.len == Self::MAX_FRAGMENTS {
+                return Err(DtbErrorKind::CapacityExceeded.into());
+            }
+            fragments.fragments[fragments.len] = Some((target.address, content));
+            fragments.len += 1;
+        }
+        Ok(fragments)
+    }
+
+    // overlay nodes which are merged into the base node whose ancestors are `addresses`
+    fn sources(&self, addresses: &[usize], names: &[&str]) -> Result<OverlayNodes<'o>, DtbError> {
+        let mut sources = OverlayNodes::new();
+        for &(target, content) in self.fragments[..self.len].iter().flatten() {
+            let Some(depth) = addresses.iter().position(|&address| address == target) else {
+                continue;
+            };
+            let mut node = Some(content);
+            for name in &names[depth + 1..] {
+                node = match node {
+                    Some(node) => child_by_name(node, name)?,
+                    None => break,
+                };
+            }
+            if let Some(node) = node {
+                sources.push(node);
+            }
+        }
+        Ok(sources)
+    }
+}
+
+// unlike find_child, the unit address is not omitted
+pub(super) fn child_by_name<'a>(
+    node: DtbNode<'a>,
+    name: &str,
+) -> Result<Option<DtbNode<'a>>, DtbError> {
+    for child in node.children() {
+        let child = child?;
+        if child.name()? == name {
+            return Ok(Some(child));
+        }
+    }
+    Ok(None)
+}
+
+// cells of the overlay to rewrite, collected before the overlay is written
+struct Patches {
+    // (offset in the overlay, new value)
+    patches: [(usize, u32); Patches::MAX_PATCHES],
+    len: usize,
+}
+
+impl Patches {
+    const MAX_PATCHES: usize = 128;
+
+    fn new() -> Self {
+        Self {
+            patches: [(0, 0); Self::MAX_PATCHES],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, offset: usize, value: u32) -> Result<(), DtbError> {
+        if self.len == Self::MAX_PATCHES {
+            return Err(DtbErrorKind::CapacityExceeded.into());
+        }
+        self.patches[self.len] = (offset, value);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(usize, u32)> {
+        self.patches[..self.len].iter()
+    }
+}
+
+// a cell of a property and its offset in the blob of `parser`
+fn read_cell(
+    parser: &DtbParser<'_>,
+    property: DtbProperty,
+    offset: usize,
+) -> Result<(usize, u32), DtbError> {
+    let cell = offset
+        .checked_add(size_of::<u32>())
+        .and_then(|end| property.value().get(offset..end))
+        .ok_or(DtbErrorKind::InvalidOverlay)?;
+    Ok((
+        cell.as_ptr() as usize - parser.dtb_header.get_address(),
+        u32::from_be_bytes(cell.try_into().unwrap()),
+    ))
+}
+
+// a phandle of the overlay moved above the ones of the base tree
+fn renumber(phandle: u32, delta: u32) -> Result<u32, DtbError> {
+    phandle
+        .checked_add(delta)
+        .filter(|&phandle| phandle != u32::MAX)
+        .ok_or_else(|| DtbErrorKind::InvalidOverlay.into())
+}
+
+impl DtbParser<'_> {
+    const NODE_SYMBOLS: &'static str = "__symbols__";
+    const NODE_FIXUPS: &'static str = "__fixups__";
+    const NODE_LOCAL_FIXUPS: &'static str = "__local_fixups__";
+
+    /// applies a compiled overlay (dtc -@) and writes the merged tree into `buf`
+    ///
+    /// the phandles of `overlay` are renumbered in place so that they do not collide with the
+    /// ones of this tree, and references to labels of this tree are resolved by __symbols__.
+    /// __symbols__ of the overlay is not merged, so overlays cannot refer to each other
+    ///
+    /// `overlay` is only written once every cell to rewrite has been found. an error after that
+    /// comes from the merge, and leaves the overlay renumbered so that it must not be applied again
+    pub fn apply_overlay<'b>(
+        &self,
+        overlay: &mut [u8],
+        buf: &'b mut [u8],
+    ) -> Result<&'b [u8], DtbError> {
+        let mut patches = Patches::new();
+        DtbParser::from_bytes(overlay)?.overlay_patches(self, &mut patches)?;
+        for &(offset, value) in patches.iter() {
+            overlay[offset..offset + size_of::<u32>()].copy_from_slice(&value.to_be_bytes());
+        }
+        self.merge_overlay(&DtbParser::from_bytes(overlay)?, buf)
+    }
+
+    fn max_phandle(&self) -> Result<u32, DtbError> {
+        let mut max = 0;
+        for token in StructTokenIter::new(self) {
+            if let StructToken::BeginNode { address } = token?
+                && let Some(phandle) = DtbNode::new(self, address).phandle()?
+                && phandle != u32::MAX
+            {
+                max = max.max(phandle);
+            }
+        }
+        Ok(max)
+    }
+
+    // every cell of the overlay which is rewritten: the phandles and the references listed in
+    // __local_fixups__ are moved above the phandles of `base`, the references of __fixups__ are
+    // resolved by __symbols__ of `base`
+    fn overlay_patches(&self, base: &DtbParser<'_>, patches: &mut Patches) -> Result<(), DtbError> {
+        let delta = base.max_phandle()?;
+        for token in StructTokenIter::new(self) {
+            if let StructToken::BeginNode { address } = token? {
+                let node = DtbNode::new(self, address);
+                for name in [Self::PROP_PHANDLE, Self::PROP_LINUX_PHANDLE] {
+                    if let Some(phandle) = node.property(name)? {
+                        let (offset, phandle) = read_cell(self, phandle, 0)?;
+                        patches.push(offset, renumber(phandle, delta)?)?;
+                    }
+                }
+            }
+        }
+        let root = self.root()?;
+        if let Some(local_fixups) = child_by_name(root, Self::NODE_LOCAL_FIXUPS)? {
+            self.local_fixup_patches(local_fixups, root, delta, patches)?;
+        }
+        self.fixup_patches(base, patches)
+    }
+
+    // __local_fixups__ has the same structure as the tree, each property lists the offsets
+    fn local_fixup_patches(
+        &self,
+        fixups: DtbNode,
+        node: DtbNode,
+        delta: u32,
+        patches: &mut Patches,
+    ) -> Result<(), DtbError> {
+        for fixup in fixups.properties() {
+            let fixup = fixup?;
+            let property = node
+                .property(fixup.name())?
+                .ok_or(DtbErrorKind::InvalidOverlay)?;
+            for offset in fixup.as_cells().ok_or(DtbErrorKind::InvalidOverlay)? {
+                let (offset, phandle) = read_cell(self, property, offset as usize)?;
+                patches.push(offset, renumber(phandle, delta)?)?;
+            }
+        }
+        for child in fixups.children() {
+            let child = child?;
+            let target = child_by_name(node, child.name()?)?.ok_or(DtbErrorKind::InvalidOverlay)?;
+            self.local_fixup_patches(child, target, delta, patches)?;
+        }
+        Ok(())
+    }
+
+    // the phandles of the labels in the base tree, each entry is "path:property:offset"
+    fn fixup_patches(&self, base: &DtbParser<'_>, patches: &mut Patches) -> Result<(), DtbError> {
+        let Some(fixups) = child_by_name(self.root()?, Self::NODE_FIXUPS)? else {
+            return Ok(());
+        };
+        let symbols =
+            child_by_name(base.root()?, Self::NODE_SYMBOLS)?.ok_or(DtbErrorKind::NotFound)?;
+        for fixup in fixups.properties() {
+            let fixup = fixup?;
+            let path = symbols
+                .property(fixup.name())?
+                .and_then(|path| path.as_str())
+                .ok_or(DtbErrorKind::NotFound)?;
+            let phandle = base
+                .find_node_by_path(path)?
+                .ok_or(DtbErrorKind::InvalidOverlay)?
+                .phandle()?
+                .ok_or(DtbErrorKind::InvalidOverlay)?;
+            for entry in fixup.as_str_list() {
+                let mut fields = entry?.rsplitn(3, ':');
+                let (Some(offset), Some(name), Some(entry_path)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(DtbErrorKind::InvalidOverlay.into());
+                };
+                let offset = offset
+                    .parse::<usize>()
+                    .map_err(|_| DtbErrorKind::InvalidOverlay)?;
+                let property = self
+                    .find_node_by_path(entry_path)?
+                    .ok_or(DtbErrorKind::InvalidOverlay)?
+                    .property(name)?
+                    .ok_or(DtbErrorKind::InvalidOverlay)?;
+                patches.push(read_cell(self, property, offset)?.0, phandle)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_overlay<'b>(
+        &self,
+        overlay: &DtbParser<'_>,
+        buf: &'b mut [u8],
+    ) -> Result<&'b [u8], DtbError> {
+        let fragments = Fragments::new(self, overlay)?;
+        let mut writer = FdtWriter::new(buf)?;
+        for (address, size) in self
+            .memory_reservations()
+            .chain(overlay.memory_reservations())
+        {
+            writer.add_reservation(address as u64, size as u64)?;
+        }
+
+        let mut names = [""; DtbNode::MAX_DEPTH];
+        let mut addresses = [0; DtbNode::MAX_DEPTH];
+        let mut depth = 0;
+        let mut sources = OverlayNodes::new();
+        // the added properties of the current node are written before its first child
+        let mut properties_pending = false;
+        for token in StructTokenIter::new(self) {
+            match token? {
+                StructToken::BeginNode { address } => {
+                    if properties_pending {
+                        let node = DtbNode::new(self, addresses[depth - 1]);
+                        Self::write_overlay_properties(&mut writer, &sources, Some(node))?;
+                    }
+                    if depth == DtbNode::MAX_DEPTH {
+                        return Err(DtbErrorKind::TooDeep.into());
+                    }
+                    names[depth] = DtbNode::new(self, address).name()?;
+                    addresses[depth] = address;
+                    depth += 1;
+                    sources = fragments.sources(&addresses[..depth], &names[..depth])?;
+                    writer.begin_node(names[depth - 1])?;
+                    properties_pending = true;
+                }
+                StructToken::Property { name, value } => {
+                    let value = match sources.property(name)? {
+                        Some(property) => property.value(),
+                        None => value,
+                    };
+                    writer.property(name, value)?;
+                }
+                StructToken::EndNode => {
+                    if depth == 0 {
+                        return Err(DtbErrorKind::UnbalancedNode.into());
+                    }
+                    let node = DtbNode::new(self, addresses[depth - 1]);
+                    // the children have overwritten the sources
+                    sources = fragments.sources(&addresses[..depth], &names[..depth])?;
+                    if properties_pending {
+                        properties_pending = false;
+                        Self::write_overlay_properties(&mut writer, &sources, Some(node))?;
+                    }
+                    Self::write_overlay_children(&mut writer, &sources, Some(node))?;
+                    writer.end_node()?;
+                    depth -= 1;
+                }
+            }
+        }
+        writer.finish(self.dtb_header.get_boot_cpuid_phys())
+    }
+
+    // writes the properties of `sources` which `base` does not have
+    fn write_overlay_properties(
+        writer: &mut FdtWriter,
+        sources: &OverlayNodes,
+        base: Option<DtbNode>,
+    ) -> Result<(), DtbError> {
+        for (i, source) in sources.iter().enumerate() {
+            for property in source.properties() {
+                let property = property?;
+                let name = property.name();
+                if let Some(base) = base
+                    && base.property(name)?.is_some()
+                {
+                    continue;
+                }
+                let mut overwritten = false;
+                for later in sources.iter().skip(i + 1) {
+                    overwritten |= later.property(name)?.is_some();
+                }
+                if !overwritten {
+                    writer.property(name, property.value())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // writes the children of `sources` which `base` does not have, merging the ones with the same name
+    fn write_overlay_children(
+        writer: &mut FdtWriter,
+        sources: &OverlayNodes,
+        base: Option<DtbNode>,
+    ) -> Result<(), DtbError> {
+        for (i, source) in sources.iter().enumerate() {
+            for child in source.children() {
+                let name = child?.name()?;
+                if let Some(base) = base
+                    && child_by_name(base, name)?.is_some()
+                {
+                    continue;
+                }
+                let mut written = false;
+                for earlier in sources.iter().take(i) {
+                    written |= child_by_name(earlier, name)?.is_some();
+                }
+                if written {
+                    continue;
+                }
+                let mut children = OverlayNodes::new();
+                for later in sources.iter().skip(i) {
+                    if let Some(child) = child_by_name(later, name)? {
+                        children.push(child);
+                    }
+                }
+                writer.begin_node(name)?;
+                Self::write_overlay_properties(writer, &children, None)?;
+                Self::write_overlay_children(writer, &children, None)?;
+                writer.end_node()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // overlay.dtbo is laid out like the output of dtc -@, fixtures_match_dtc checks it against dtc
+    #[test]
+    fn apply_compiled_overlay() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let mut overlay = std::fs::read("test/overlay.dtbo").expect("failed to load dtbo files");
+        let parser = DtbParser::from_bytes(&test_data).unwrap();
+        let max_phandle = parser.max_phandle().unwrap();
+
+        // the references are left to the fixups in the layout of dtc -@
+        {
+            let overlay = DtbParser::from_bytes(&overlay).unwrap();
+            let target = overlay.find_node_by_path("/fragment@0").unwrap().unwrap();
+            assert_eq!(
+                target.property("target").unwrap().unwrap().as_u32(),
+                Some(u32::MAX)
+            );
+            let fixups = overlay.find_node_by_path("/__fixups__").unwrap().unwrap();
+            assert_eq!(
+                fixups.property("rp1_uart1").unwrap().unwrap().as_str(),
+                Some("/fragment@0:target:0")
+            );
+            let local_fixups = overlay
+                .find_node_by_path("/__local_fixups__/fragment@1/__overlay__/hat-sensor")
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                local_fixups.property("clocks").unwrap().unwrap().as_u32(),
+                Some(0)
+            );
+        }
+
+        let mut buf = vec![0u8; test_data.len() + overlay.len()];
+        let blob = parser.apply_overlay(&mut overlay, &mut buf).unwrap();
+        let merged = DtbParser::from_bytes(blob).unwrap();
+
+        // fragment@0: properties of an existing node are replaced, fixups point to the base tree
+        let uart1 = merged
+            .find_node_by_path("/axi/pcie@1000120000/pci@0,0/rp1@0/serial@c040034000")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            uart1.property("status").unwrap().unwrap().as_str(),
+            Some("okay")
+        );
+        let pin_group = parser
+            .find_node_by_path("/axi/pcie@1000120000/pci@0,0/rp1@0/gpio@c0400d0000/rp1_uart1_0_1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            uart1.property("pinctrl-0").unwrap().unwrap().as_u32(),
+            pin_group.phandle().unwrap()
+        );
+        assert!(uart1.is_compatible("arm,pl011-axi").unwrap());
+
+        // fragment@1: new nodes get phandles above the base ones, local references follow them
+        let clock = merged.find_node_by_path("/hat-clock").unwrap().unwrap();
+        let clock_phandle = clock.phandle().unwrap().unwrap();
+        assert!(clock_phandle > max_phandle);
+        let sensor = merged.find_node_by_path("/hat-sensor").unwrap().unwrap();
+        assert_eq!(
+            sensor.property("clocks").unwrap().unwrap().as_u32(),
+            Some(clock_phandle)
+        );
+        let interrupt = sensor.interrupts().unwrap().next().unwrap().unwrap();
+        assert_eq!(interrupt.controller().name().unwrap(), "gpio@c0400d0000");
+        assert_eq!(interrupt.specifier(), [4, 8]);
+
+        // fragment@2: a child of the target is merged
+        let uart = merged.find_node_by_alias("serial10").unwrap().unwrap();
+        assert_eq!(
+            uart.property("current-speed").unwrap().unwrap().as_u32(),
+            Some(115200)
+        );
+        assert!(uart.is_compatible("arm,pl011").unwrap());
+
+        // the overlay metadata is not copied
+        let root = merged.root().unwrap();
+        for name in ["fragment@0", "__fixups__", "__local_fixups__"] {
+            assert!(child_by_name(root, name).unwrap().is_none());
+        }
+        assert_eq!(merged.max_phandle().unwrap(), clock_phandle);
+
+        // the overlay itself has been renumbered
+        let overlay = DtbParser::from_bytes(&overlay).unwrap();
+        let clock = overlay
+            .find_node_by_path("/fragment@1/__overlay__/hat-clock")
+            .unwrap()
+            .unwrap();
+        assert_eq!(clock.phandle().unwrap(), Some(clock_phandle));
+
+        // a phandle which overflows when it is moved above the ones of the base tree, the one
+        // before it is not rewritten either
+        let mut overlay = [0u8; 256];
+        let mut writer = FdtWriter::new(&mut overlay).unwrap();
+        writer.begin_node("").unwrap();
+        writer.begin_node("first").unwrap();
+        writer.property_u32("phandle", 1).unwrap();
+        writer.end_node().unwrap();
+        writer.begin_node("second").unwrap();
+        writer.property_u32("phandle", u32::MAX - 1).unwrap();
+        writer.end_node().unwrap();
+        writer.end_node().unwrap();
+        writer.finish(0).unwrap();
+        let original = overlay;
+        let error = parser.apply_overlay(&mut overlay, &mut buf).err().unwrap();
+        assert_eq!(error.kind(), DtbErrorKind::InvalidOverlay);
+        assert_eq!(overlay, original);
+    }
+}
@@ -0,0 +1,245 @@
+// pin configuration of the generic pinctrl bindings
+//
+// a client node selects its states with 'pinctrl-names' and 'pinctrl-<n>'. each phandle of a
+// state points to a configuration node below the pin controller, which either has 'pins' itself
+// or groups a configuration per child node like the RP1 UART pins
+
+use super::*;
+
+/// pull resistor of the pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bias {
+    Disable,
+    PullUp,
+    PullDown,
+}
+
+/// one configuration node of a pinctrl state
+#[derive(Clone, Copy, Debug)]
+pub struct PinConfig<'a> {
+    node: DtbNode<'a>,
+    controller: DtbNode<'a>,
+}
+
+impl<'a> PinConfig<'a> {
+    const PROP_PINS: &'static str = "pins";
+    const PROP_FUNCTION: &'static str = "function";
+    const PROP_BIAS_DISABLE: &'static str = "bias-disable";
+    const PROP_BIAS_PULL_UP: &'static str = "bias-pull-up";
+    const PROP_BIAS_PULL_DOWN: &'static str = "bias-pull-down";
+    const PROP_DRIVE_STRENGTH: &'static str = "drive-strength";
+
+    pub fn node(&self) -> DtbNode<'a> {
+        self.node
+    }
+
+    /// the pin controller which owns the pins
+    pub fn controller(&self) -> DtbNode<'a> {
+        self.controller
+    }
+
+    /// names of the pins, e.g. "gpio14"
+    pub fn pins(&self) -> Result<StrListIter<'a>, DtbError> {
+        Ok(self
+            .node
+            .property(Self::PROP_PINS)?
+            .ok_or_else(|| self.node.error(DtbErrorKind::NotFound))?
+            .as_str_list())
+    }
+
+    /// the function muxed to the pins, None leaves the mux as it is
+    pub fn function(&self) -> Result<Option<&'a str>, DtbError> {
+        self.node
+            .property(Self::PROP_FUNCTION)?
+            .map(|function| {
+                function
+                    .as_str()
+                    .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty))
+            })
+            .transpose()
+    }
+
+    /// None leaves the pull resistor as it is
+    pub fn bias(&self) -> Result<Option<Bias>, DtbError> {
+        let mut bias = None;
+        for (name, value) in [
+            (Self::PROP_BIAS_DISABLE, Bias::Disable),
+            (Self::PROP_BIAS_PULL_UP, Bias::PullUp),
+            (Self::PROP_BIAS_PULL_DOWN, Bias::PullDown),
+        ] {
+            if self.node.property(name)?.is_some() {
+                // the bindings allow only one of them
+                if bias.is_some() {
+                    return Err(self.node.error(DtbErrorKind::InvalidProperty));
+                }
+                bias = Some(value);
+            }
+        }
+        Ok(bias)
+    }
+
+    /// drive strength in mA
+    pub fn drive_strength(&self) -> Result<Option<u32>, DtbError> {
+        self.node
+            .property(Self::PROP_DRIVE_STRENGTH)?
+            .map(|strength| {
+                strength
+                    .as_u32()
+                    .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty))
+            })
+            .transpose()
+    }
+}
+
+impl<'a> DtbNode<'a> {
+    const PROP_PINCTRL_NAMES: &'static str = "pinctrl-names";
+    const PROP_PINCTRL_PREFIX: &'static str = "pinctrl-";
+
+    /// iterates over the configurations of the pinctrl state `name`, e.g. "default"
+    ///
+    /// None when the node does not have the state
+    pub fn pinctrl(&self, name: &str) -> Result<Option<PinctrlIter<'a>>, DtbError> {
+        let Some(names) = self.property(Self::PROP_PINCTRL_NAMES)? else {
+            return Ok(None);
+        };
+        let mut index = None;
+        for (i, state) in names.as_str_list().enumerate() {
+            if state? == name {
+                index = Some(i);
+                break;
+            }
+        }
+        let Some(index) = index else {
+            return Ok(None);
+        };
+        // 'pinctrl-<index>' is searched by its name as there is no buffer to format it
+        for property in self.properties() {
+            let property = property?;
+            let matched = property
+                .name()
+                .strip_prefix(Self::PROP_PINCTRL_PREFIX)
+                .and_then(|i| i.parse::<usize>().ok())
+                == Some(index);
+            if matched {
+                let phandles = property
+                    .as_cells()
+                    .ok_or_else(|| self.error(DtbErrorKind::InvalidProperty))?;
+                return Ok(Some(PinctrlIter {
+                    node: *self,
+                    phandles: Some(phandles),
+                    group: None,
+                }));
+            }
+        }
+        Err(self.error(DtbErrorKind::NotFound))
+    }
+}
+
+/// iterator over the configurations of a pinctrl state
+pub struct PinctrlIter<'a> {
+    node: DtbNode<'a>,
+    phandles: Option<Cells<'a>>,
+    // the children of a group node and its pin controller
+    group: Option<(ChildIter<'a>, DtbNode<'a>)>,
+}
+
+impl<'a> PinctrlIter<'a> {
+    fn next_internal(&mut self) -> Result<Option<PinConfig<'a>>, DtbError> {
+        loop {
+            if let Some((children, controller)) = &mut self.group {
+                match children.next() {
+                    Some(child) => {
+                        return Ok(Some(PinConfig {
+                            node: child?,
+                            controller: *controller,
+                        }));
+                    }
+                    None => self.group = None,
+                }
+            }
+            let Some(phandle) = self.phandles.as_mut().and_then(|phandles| phandles.next()) else {
+                return Ok(None);
+            };
+            let node = self
+                .node
+                .parser
+                .find_node_by_phandle(phandle)?
+                .ok_or_else(|| self.node.error(DtbErrorKind::UnknownPhandle))?;
+            let controller = node
+                .parent()?
+                .ok_or_else(|| node.error(DtbErrorKind::InvalidProperty))?;
+            if node.property(PinConfig::PROP_PINS)?.is_some() {
+                return Ok(Some(PinConfig { node, controller }));
+            }
+            self.group = Some((node.children(), controller));
+        }
+    }
+}
+
+impl<'a> Iterator for PinctrlIter<'a> {
+    type Item = Result<PinConfig<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_internal();
+        if result.is_err() {
+            self.phandles = None;
+            self.group = None;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_pin_configs() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+        let gpio = parser
+            .find_node_by_path("/axi/pcie@1000120000/pci@0,0/rp1@0/gpio@c0400d0000")
+            .unwrap()
+            .unwrap();
+
+        // a group with a configuration per child
+        let uart0 = parser.find_node_by_alias("uart0").unwrap().unwrap();
+        let configs: Vec<PinConfig> = uart0
+            .pinctrl("default")
+            .unwrap()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(configs.len(), 2);
+        let (txd, rxd) = (configs[0], configs[1]);
+        assert_eq!(txd.node().name(), Ok("pin_txd"));
+        assert_eq!(txd.controller().name(), gpio.name());
+        assert_eq!(
+            txd.pins().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec!["gpio14"])
+        );
+        assert_eq!(txd.function(), Ok(Some("uart0")));
+        assert_eq!(txd.bias(), Ok(Some(Bias::Disable)));
+        assert_eq!(rxd.bias(), Ok(Some(Bias::PullUp)));
+        assert_eq!(rxd.drive_strength(), Ok(None));
+        assert!(uart0.pinctrl("sleep").unwrap().is_none());
+
+        // the configuration itself, on a disabled node
+        let uart1 = parser
+            .find_node_by_path("/axi/pcie@1000120000/pci@0,0/rp1@0/serial@c040034000")
+            .unwrap()
+            .unwrap();
+        let mut configs = uart1.pinctrl("default").unwrap().unwrap();
+        let config = configs.next().unwrap().unwrap();
+        assert_eq!(config.node().name(), Ok("rp1_uart1_0_1"));
+        assert_eq!(config.controller().name(), gpio.name());
+        assert_eq!(
+            config.pins().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec!["gpio0", "gpio1"])
+        );
+        assert_eq!(config.drive_strength(), Ok(Some(8)));
+        assert!(configs.next().is_none());
+
+        assert!(gpio.pinctrl("default").unwrap().is_none());
+    }
+}
@@ -0,0 +1,162 @@
+// memory reservation block and /reserved-memory node
+
+use super::*;
+
+impl DtbParser<'_> {
+    const NODE_RESERVED_MEMORY: &'static str = "reserved-memory";
+
+    /// (address, size) of the dtb itself
+    pub fn dtb_region(&self) -> (usize, usize) {
+        (
+            self.dtb_header.get_address(),
+            self.dtb_header.get_total_size(),
+        )
+    }
+
+    /// iterates over the entries of the memory reservation block (/memreserve/)
+    pub fn memory_reservations(&self) -> MemReserveIter<'_> {
+        MemReserveIter {
+            entries: self.dtb_header.get_memory_reservation_block(),
+        }
+    }
+
+    /// iterates over the children of /reserved-memory
+    pub fn reserved_memory(&self) -> Result<ReservedMemoryIter<'_>, DtbError> {
+        Ok(ReservedMemoryIter {
+            children: self
+                .root()?
+                .find_child(Self::NODE_RESERVED_MEMORY)?
+                .map(|node| node.children()),
+        })
+    }
+}
+
+/// iterator over the (address, size) pairs of the memory reservation block
+pub struct MemReserveIter<'a> {
+    // up to the end of the blob, a block without the terminator ends there
+    entries: &'a [u8],
+}
+
+impl Iterator for MemReserveIter<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the block is terminated by an entry whose address and size are both 0
+        let (entry, rest) = self
+            .entries
+            .split_first_chunk::<{ size_of::<FdtReserveEntry>() }>()?;
+        let entry = FdtReserveEntry::read(entry);
+        if entry.get_address() == 0 && entry.get_size() == 0 {
+            self.entries = &[];
+            return None;
+        }
+        self.entries = rest;
+        Some((entry.get_address() as usize, entry.get_size() as usize))
+    }
+}
+
+/// a child node of /reserved-memory
+#[derive(Clone, Copy, Debug)]
+pub struct ReservedMemory<'a> {
+    node: DtbNode<'a>,
+}
+
+impl<'a> ReservedMemory<'a> {
+    const PROP_SIZE: &'static str = "size";
+    const PROP_NO_MAP: &'static str = "no-map";
+    const PROP_REUSABLE: &'static str = "reusable";
+
+    pub fn node(&self) -> DtbNode<'a> {
+        self.node
+    }
+
+    /// statically placed regions, empty when the region is allocated dynamically
+    pub fn reg(&self) -> Result<RegIter<'a>, DtbError> {
+        self.node.reg()
+    }
+
+    /// size of a dynamically allocated region ('size' property)
+    pub fn size(&self) -> Result<Option<usize>, DtbError> {
+        self.node
+            .property(Self::PROP_SIZE)?
+            .map(|size| {
+                size.as_u64()
+                    .map(|s| s as usize)
+                    .ok_or_else(|| self.node.error(DtbErrorKind::InvalidProperty))
+            })
+            .transpose()
+    }
+
+    /// the region must not be mapped by the operating system
+    pub fn no_map(&self) -> Result<bool, DtbError> {
+        Ok(self.node.property(Self::PROP_NO_MAP)?.is_some())
+    }
+
+    /// the operating system can use the region as long as the driver owning it allows
+    pub fn reusable(&self) -> Result<bool, DtbError> {
+        Ok(self.node.property(Self::PROP_REUSABLE)?.is_some())
+    }
+}
+
+/// iterator over the children of /reserved-memory
+pub struct ReservedMemoryIter<'a> {
+    // None when /reserved-memory does not exist
+    children: Option<ChildIter<'a>>,
+}
+
+impl<'a> Iterator for ReservedMemoryIter<'a> {
+    type Item = Result<ReservedMemory<'a>, DtbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.children
+                .as_mut()?
+                .next()?
+                .map(|node| ReservedMemory { node }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_reserved_memory() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        assert_eq!(
+            parser.dtb_region(),
+            (test_data.as_ptr() as usize, test_data.len())
+        );
+        assert_eq!(
+            parser.memory_reservations().collect::<Vec<_>>(),
+            [(0x0, 0x1000)]
+        );
+
+        let regions: Vec<_> = parser
+            .reserved_memory()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(regions.len(), 2);
+
+        let atf = regions[0];
+        assert_eq!(atf.node().name().unwrap(), "atf@0");
+        assert_eq!(
+            atf.reg().unwrap().map(Result::unwrap).collect::<Vec<_>>(),
+            [(0x0, 0x80000)]
+        );
+        assert_eq!(atf.size().unwrap(), None);
+        assert!(atf.no_map().unwrap());
+        assert!(!atf.reusable().unwrap());
+
+        let cma = regions[1];
+        assert_eq!(cma.node().name().unwrap(), "linux,cma");
+        assert_eq!(cma.reg().unwrap().count(), 0);
+        assert_eq!(cma.size().unwrap(), Some(0x400_0000));
+        assert!(!cma.no_map().unwrap());
+        assert!(cma.reusable().unwrap());
+    }
+}
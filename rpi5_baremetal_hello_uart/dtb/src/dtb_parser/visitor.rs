@@ -0,0 +1,177 @@
+// streaming walk over the whole structure block
+
+use super::*;
+
+/// callbacks of DtbParser::walk, called in the order of the structure block
+///
+/// the root node has an empty name and the depth 0, returning `ControlFlow::Break` stops the walk
+pub trait DtbVisitor<'a> {
+    fn begin_node(&mut self, _name: &'a str, _depth: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// called for each property of the node most recently begun, before any of its children
+    fn property(&mut self, _name: &'a str, _value: &'a [u8]) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// depth is the same as the one given to the matching begin_node
+    fn end_node(&mut self, _depth: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+impl DtbParser<'_> {
+    /// walks the structure block and passes every token to the visitor
+    ///
+    /// the structure is validated while walking, so an error can be returned after some
+    /// callbacks were already called
+    pub fn walk<'a, V>(&'a self, visitor: &mut V) -> Result<ControlFlow<()>, DtbError>
+    where
+        V: DtbVisitor<'a> + ?Sized,
+    {
+        let mut tokens = StructTokenIter::new(self);
+        // depth of the next node, 0 before the root and after its end
+        let mut depth = 0;
+        let mut root_done = false;
+        // properties have to come before the children of the node
+        let mut properties_allowed = false;
+        loop {
+            let address = tokens.pointer;
+            let Some(token) = tokens.next().transpose()? else {
+                break;
+            };
+            let flow = match token {
+                StructToken::BeginNode { address } => {
+                    if root_done {
+                        return Err(self.error_at(DtbErrorKind::UnexpectedToken, address));
+                    }
+                    let name = DtbNode::new(self, address).name()?;
+                    properties_allowed = true;
+                    depth += 1;
+                    visitor.begin_node(name, depth - 1)
+                }
+                StructToken::Property { name, value } => {
+                    if !properties_allowed {
+                        return Err(self.error_at(DtbErrorKind::UnexpectedToken, address));
+                    }
+                    visitor.property(name, value)
+                }
+                StructToken::EndNode => {
+                    if depth == 0 {
+                        return Err(self.error_at(DtbErrorKind::UnbalancedNode, address));
+                    }
+                    properties_allowed = false;
+                    depth -= 1;
+                    root_done = depth == 0;
+                    visitor.end_node(depth)
+                }
+            };
+            if flow.is_break() {
+                return Ok(flow);
+            }
+        }
+        if !root_done {
+            return Err(self.error_at(DtbErrorKind::UnbalancedNode, tokens.pointer));
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        nodes: usize,
+        properties: usize,
+        max_depth: usize,
+        open: Vec<usize>,
+    }
+
+    impl<'a> DtbVisitor<'a> for Counter {
+        fn begin_node(&mut self, _name: &'a str, depth: usize) -> ControlFlow<()> {
+            self.nodes += 1;
+            self.max_depth = self.max_depth.max(depth);
+            self.open.push(depth);
+            ControlFlow::Continue(())
+        }
+
+        fn property(&mut self, _name: &'a str, _value: &'a [u8]) -> ControlFlow<()> {
+            self.properties += 1;
+            ControlFlow::Continue(())
+        }
+
+        fn end_node(&mut self, depth: usize) -> ControlFlow<()> {
+            assert_eq!(self.open.pop(), Some(depth));
+            ControlFlow::Continue(())
+        }
+    }
+
+    // stops at the first node with the name and keeps its 'reg'
+    struct Search<'a> {
+        name: &'a str,
+        found: bool,
+        reg: Option<&'a [u8]>,
+    }
+
+    impl<'a> DtbVisitor<'a> for Search<'a> {
+        fn begin_node(&mut self, name: &'a str, _depth: usize) -> ControlFlow<()> {
+            self.found = name == self.name;
+            ControlFlow::Continue(())
+        }
+
+        fn property(&mut self, name: &'a str, value: &'a [u8]) -> ControlFlow<()> {
+            if self.found && name == DtbNode::PROP_REG {
+                self.reg = Some(value);
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn walk_structure_block() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let mut counter = Counter::default();
+        assert_eq!(
+            parser.walk(&mut counter).unwrap(),
+            ControlFlow::Continue(())
+        );
+        assert!(counter.open.is_empty());
+        let nodes = StructTokenIter::new(&parser)
+            .filter(|t| matches!(t, Ok(StructToken::BeginNode { .. })))
+            .count();
+        assert_eq!(counter.nodes, nodes);
+        assert!(counter.properties > counter.nodes);
+        assert!(counter.max_depth >= 2);
+
+        let mut search = Search {
+            name: "serial@7d001000",
+            found: false,
+            reg: None,
+        };
+        assert_eq!(parser.walk(&mut search).unwrap(), ControlFlow::Break(()));
+        let uart = parser.find_node_by_path("serial10").unwrap().unwrap();
+        assert_eq!(
+            search.reg,
+            Some(uart.property(DtbNode::PROP_REG).unwrap().unwrap().value())
+        );
+
+        // a property after a child node is rejected
+        let mut buf = [0u8; 256];
+        let mut writer = FdtWriter::new(&mut buf).unwrap();
+        writer.begin_node("").unwrap();
+        writer.begin_node("child").unwrap();
+        writer.end_node().unwrap();
+        writer.property("late", &[]).unwrap();
+        writer.end_node().unwrap();
+        let blob = writer.finish(0).unwrap();
+        let parser = unsafe { DtbParser::init(blob.as_ptr() as usize) }.unwrap();
+        let error = parser.walk(&mut Counter::default()).err().unwrap();
+        assert_eq!(error.kind(), DtbErrorKind::UnexpectedToken);
+    }
+}
@@ -0,0 +1,755 @@
+// FDT writer and the modification of an existing tree
+
+use super::*;
+
+/// writes a version 17 flattened device tree into a caller supplied buffer
+///
+/// same as the sequential write interface of libfdt, the strings are stored downward from the
+/// end of the buffer while the tree is being built, then moved right after the structure block
+/// by `finish`. property names are deduplicated
+pub struct FdtWriter<'b> {
+    buf: &'b mut [u8],
+    pointer: usize,
+    // None until the first node is written, reservations can be added only before that
+    struct_start: Option<usize>,
+    strings_size: usize,
+    depth: usize,
+    root_written: bool,
+}
+
+impl<'b> FdtWriter<'b> {
+    const HEADER_SIZE: usize = 40;
+    const RESERVE_ENTRY_SIZE: usize = 16;
+    const LAST_COMP_VERSION: u32 = 16;
+
+    pub fn new(buf: &'b mut [u8]) -> Result<Self, DtbError> {
+        if buf.len() < Self::HEADER_SIZE {
+            return Err(DtbErrorKind::BufferTooSmall.into());
+        }
+        Ok(Self {
+            buf,
+            pointer: Self::HEADER_SIZE,
+            struct_start: None,
+            strings_size: 0,
+            depth: 0,
+            root_written: false,
+        })
+    }
+
+    // free space between the structure block and the strings
+    fn reserve(&mut self, size: usize) -> Result<usize, DtbError> {
+        let start = self.pointer;
+        if start + size > self.buf.len() - self.strings_size {
+            return Err(DtbErrorKind::BufferTooSmall.into());
+        }
+        self.pointer += size;
+        Ok(start)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), DtbError> {
+        let start = self.reserve(bytes.len())?;
+        self.buf[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_u32(&mut self, value: u32) -> Result<(), DtbError> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    fn pad(&mut self) -> Result<(), DtbError> {
+        let size = self.pointer.next_multiple_of(DtbParser::ALIGNMENT as usize) - self.pointer;
+        let start = self.reserve(size)?;
+        self.buf[start..start + size].fill(0);
+        Ok(())
+    }
+
+    pub fn add_reservation(&mut self, address: u64, size: u64) -> Result<(), DtbError> {
+        if self.struct_start.is_some() {
+            return Err(DtbErrorKind::InvalidArgument.into());
+        }
+        self.write_bytes(&address.to_be_bytes())?;
+        self.write_bytes(&size.to_be_bytes())
+    }
+
+    pub fn begin_node(&mut self, name: &str) -> Result<(), DtbError> {
+        if self.struct_start.is_none() {
+            // terminator of the memory reservation block
+            self.write_bytes(&[0; Self::RESERVE_ENTRY_SIZE])?;
+            self.struct_start = Some(self.pointer);
+        }
+        if self.depth == 0 {
+            if self.root_written {
+                return Err(DtbErrorKind::UnexpectedToken.into());
+            }
+            self.root_written = true;
+        }
+        if name.contains('\0') {
+            return Err(DtbErrorKind::InvalidArgument.into());
+        }
+        self.write_bytes(&DtbParser::FDT_BEGIN_NODE)?;
+        self.write_bytes(name.as_bytes())?;
+        self.write_bytes(&[0])?;
+        self.pad()?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn end_node(&mut self) -> Result<(), DtbError> {
+        self.depth = self
+            .depth
+            .checked_sub(1)
+            .ok_or(DtbErrorKind::InvalidArgument)?;
+        self.write_bytes(&DtbParser::FDT_END_NODE)
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) -> Result<(), DtbError> {
+        self.write_property(name, &[value])
+    }
+
+    pub fn property_u32(&mut self, name: &str, value: u32) -> Result<(), DtbError> {
+        self.property(name, &value.to_be_bytes())
+    }
+
+    pub fn property_u64(&mut self, name: &str, value: u64) -> Result<(), DtbError> {
+        self.property(name, &value.to_be_bytes())
+    }
+
+    /// writes a null terminated string
+    pub fn property_str(&mut self, name: &str, value: &str) -> Result<(), DtbError> {
+        self.write_property(name, &[value.as_bytes(), &[0]])
+    }
+
+    // the value is the concatenation of `parts`
+    fn write_property(&mut self, name: &str, parts: &[&[u8]]) -> Result<(), DtbError> {
+        if self.depth == 0 {
+            return Err(DtbErrorKind::UnexpectedToken.into());
+        }
+        let len = parts.iter().map(|part| part.len()).sum::<usize>();
+        let name_offset = self.add_string(name)?;
+        self.write_bytes(&DtbParser::FDT_PROP)?;
+        self.write_u32(u32::try_from(len).map_err(|_| DtbErrorKind::Overflow)?)?;
+        self.write_u32(name_offset)?;
+        for part in parts {
+            self.write_bytes(part)?;
+        }
+        self.pad()
+    }
+
+    // returns the offset from the end of the buffer as a negative number,
+    // it is fixed up by finish once the size of the strings block is known
+    fn add_string(&mut self, name: &str) -> Result<u32, DtbError> {
+        let strings_start = self.buf.len() - self.strings_size;
+        let needle_len = name.len() + 1;
+        // a suffix of an existing string can be shared as well
+        let found = self.buf[strings_start..]
+            .windows(needle_len)
+            .position(|s| &s[..name.len()] == name.as_bytes() && s[name.len()] == 0);
+        let position = match found {
+            Some(position) => strings_start + position,
+            None => {
+                if self.pointer + needle_len > strings_start {
+                    return Err(DtbErrorKind::BufferTooSmall.into());
+                }
+                let position = strings_start - needle_len;
+                self.buf[position..strings_start - 1].copy_from_slice(name.as_bytes());
+                self.buf[strings_start - 1] = 0;
+                self.strings_size += needle_len;
+                position
+            }
+        };
+        Ok((position as isize - self.buf.len() as isize) as i32 as u32)
+    }
+
+    /// completes the blob and returns it
+    pub fn finish(mut self, boot_cpuid_phys: u32) -> Result<&'b [u8], DtbError> {
+        if self.depth != 0 || !self.root_written {
+            return Err(DtbErrorKind::InvalidArgument.into());
+        }
+        let struct_start = self.struct_start.ok_or(DtbErrorKind::InvalidArgument)?;
+        self.write_bytes(&DtbParser::FDT_END)?;
+        let struct_size = self.pointer - struct_start;
+
+        // move the strings right after the structure block
+        let strings_start = self.pointer;
+        let strings_size = self.strings_size;
+        let len = self.buf.len();
+        self.buf.copy_within(len - strings_size..len, strings_start);
+        self.fix_name_offsets(struct_start, strings_size as u32)?;
+
+        let total_size = strings_start + strings_size;
+        let header = [
+            Dtb::DTB_HEADER_MAGIC,
+            total_size as u32,
+            struct_start as u32,
+            strings_start as u32,
+            Self::HEADER_SIZE as u32,
+            Dtb::DTB_VERSION,
+            Self::LAST_COMP_VERSION,
+            boot_cpuid_phys,
+            strings_size as u32,
+            struct_size as u32,
+        ];
+        for (i, value) in header.into_iter().enumerate() {
+            self.buf[i * size_of::<u32>()..(i + 1) * size_of::<u32>()]
+                .copy_from_slice(&value.to_be_bytes());
+        }
+        Ok(&self.buf[..total_size])
+    }
+
+    fn fix_name_offsets(&mut self, struct_start: usize, strings_size: u32) -> Result<(), DtbError> {
+        let read_u32 = |buf: &[u8], offset: usize| {
+            u32::from_be_bytes(buf[offset..offset + size_of::<u32>()].try_into().unwrap())
+        };
+        let align = |offset: usize| offset.next_multiple_of(DtbParser::ALIGNMENT as usize);
+        let mut offset = struct_start;
+        loop {
+            let token: [u8; DtbParser::SIZEOF_FDT_TOKEN] = self.buf
+                [offset..offset + DtbParser::SIZEOF_FDT_TOKEN]
+                .try_into()
+                .unwrap();
+            offset += DtbParser::SIZEOF_FDT_TOKEN;
+            match token {
+                DtbParser::FDT_BEGIN_NODE => {
+                    let name_len = self.buf[offset..]
+                        .iter()
+                        .position(|&c| c == 0)
+                        .ok_or(DtbErrorKind::InvalidString)?;
+                    offset = align(offset + name_len + 1);
+                }
+                DtbParser::FDT_PROP => {
+                    let len = read_u32(self.buf, offset) as usize;
+                    let name_offset = read_u32(self.buf, offset + size_of::<u32>());
+                    self.buf[offset + size_of::<u32>()..offset + 2 * size_of::<u32>()]
+                        .copy_from_slice(&name_offset.wrapping_add(strings_size).to_be_bytes());
+                    offset = align(offset + size_of::<FdtProperty>() + len);
+                }
+                DtbParser::FDT_END_NODE | DtbParser::FDT_NOP => {}
+                DtbParser::FDT_END => return Ok(()),
+                _ => return Err(DtbErrorKind::UnexpectedToken.into()),
+            }
+        }
+    }
+}
+
+/// a modification applied by `DtbParser::write_modified`
+///
+/// paths are absolute and the unit address can be omitted as in `find_node_by_path`, except for
+/// AddNode and edits of the nodes it adds, whose paths are compared as they are. when a
+/// property is set or removed more than once, the last edit wins
+#[derive(Clone, Copy, Debug)]
+pub enum DtbEdit<'e> {
+    /// adds the property, or replaces its value when it already exists
+    SetProperty {
+        path: &'e str,
+        name: &'e str,
+        value: &'e [u8],
+    },
+    RemoveProperty {
+        path: &'e str,
+        name: &'e str,
+    },
+    /// adds an empty node, the parent must exist or be added by another edit
+    ///
+    /// nothing is added when a node of exactly the same name already exists
+    AddNode {
+        path: &'e str,
+    },
+    /// removes the node and all of its children
+    RemoveNode {
+        path: &'e str,
+    },
+    AddReservation {
+        address: u64,
+        size: u64,
+    },
+}
+
+// the node an edit applies to, resolved once before the tree is written
+#[derive(Clone, Copy, PartialEq)]
+enum EditTarget<'e> {
+    // a node of the original tree, by the address of its FDT_BEGIN_NODE token
+    Node(usize),
+    // a node added by AddNode, by its path
+    Added(&'e str),
+    // the edit writes nothing
+    None,
+}
+
+// splits "/a/b" into ("/a", "b")
+fn split_parent(path: &str) -> Option<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/')?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((if parent.is_empty() { "/" } else { parent }, name))
+}
+
+// the last SetProperty or RemoveProperty of `name` on `node`, Some(None) when it is removed
+fn property_edit<'e>(
+    edits: &[DtbEdit<'e>],
+    targets: &[EditTarget],
+    node: EditTarget,
+    name: &str,
+) -> Option<Option<&'e [u8]>> {
+    edits
+        .iter()
+        .zip(targets)
+        .rev()
+        .find_map(|(edit, &target)| match *edit {
+            DtbEdit::SetProperty { name: n, value, .. } if n == name && target == node => {
+                Some(Some(value))
+            }
+            DtbEdit::RemoveProperty { name: n, .. } if n == name && target == node => Some(None),
+            _ => None,
+        })
+}
+
+impl DtbParser<'_> {
+    const MAX_EDITS: usize = 32;
+
+    /// copies this tree into `buf` applying `edits`, and returns the new blob
+    ///
+    /// e.g. /chosen can be fixed up before jumping into a kernel. up to MAX_EDITS (32) edits can
+    /// be applied at once
+    pub fn write_modified<'b>(
+        &self,
+        buf: &'b mut [u8],
+        edits: &[DtbEdit],
+    ) -> Result<&'b [u8], DtbError> {
+        let mut targets = [EditTarget::None; Self::MAX_EDITS];
+        let targets = self.resolve_edits(edits, &mut targets)?;
+        let mut writer = FdtWriter::new(buf)?;
+        for (address, size) in self.memory_reservations() {
+            writer.add_reservation(address as u64, size as u64)?;
+        }
+        for edit in edits {
+            if let DtbEdit::AddReservation { address, size } = *edit {
+                writer.add_reservation(address, size)?;
+            }
+        }
+
+        let mut addresses = [0; DtbNode::MAX_DEPTH];
+        let mut depth = 0;
+        // nesting level inside a removed node
+        let mut skip = 0;
+        // the added properties of the current node are written before its first child
+        let mut properties_pending = false;
+        for token in StructTokenIter::new(self) {
+            match token? {
+                StructToken::BeginNode { address } => {
+                    if skip > 0 {
+                        skip += 1;
+                        continue;
+                    }
+                    if properties_pending {
+                        properties_pending = false;
+                        let node = EditTarget::Node(addresses[depth - 1]);
+                        self.write_added_properties(&mut writer, edits, targets, node)?;
+                    }
+                    if depth == DtbNode::MAX_DEPTH {
+                        return Err(DtbErrorKind::TooDeep.into());
+                    }
+                    if edits.iter().zip(targets).any(|(edit, &target)| {
+                        matches!(edit, DtbEdit::RemoveNode { .. })
+                            && target == EditTarget::Node(address)
+                    }) {
+                        skip = 1;
+                        continue;
+                    }
+                    writer.begin_node(DtbNode::new(self, address).name()?)?;
+                    addresses[depth] = address;
+                    depth += 1;
+                    properties_pending = true;
+                }
+                StructToken::Property { name, value } => {
+                    if skip > 0 {
+                        continue;
+                    }
+                    let node = EditTarget::Node(addresses[depth - 1]);
+                    match property_edit(edits, targets, node, name) {
+                        Some(None) => {}
+                        Some(Some(value)) => writer.property(name, value)?,
+                        None => writer.property(name, value)?,
+                    }
+                }
+                StructToken::EndNode => {
+                    if skip > 0 {
+                        skip -= 1;
+                        continue;
+                    }
+                    if depth == 0 {
+                        return Err(DtbErrorKind::UnbalancedNode.into());
+                    }
+                    let node = EditTarget::Node(addresses[depth - 1]);
+                    if properties_pending {
+                        properties_pending = false;
+                        self.write_added_properties(&mut writer, edits, targets, node)?;
+                    }
+                    self.write_added_nodes(&mut writer, edits, targets, node, depth)?;
+                    writer.end_node()?;
+                    depth -= 1;
+                }
+            }
+        }
+        writer.finish(self.dtb_header.get_boot_cpuid_phys())
+    }
+
+    // unlike find_node_by_path, every unit address has to match
+    fn find_node_by_exact_path(&self, path: &str) -> Result<Option<DtbNode<'_>>, DtbError> {
+        let Some(path) = path.strip_prefix('/') else {
+            return Ok(None);
+        };
+        let mut node = self.root()?;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            match super::overlay::child_by_name(node, component)? {
+                Some(child) => node = child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(node))
+    }
+
+    // resolves every edit to the node it applies to, and rejects the ones whose node does not
+    // exist
+    fn resolve_edits<'e, 't>(
+        &self,
+        edits: &[DtbEdit<'e>],
+        targets: &'t mut [EditTarget<'e>],
+    ) -> Result<&'t [EditTarget<'e>], DtbError> {
+        if edits.len() > targets.len() {
+            return Err(DtbErrorKind::CapacityExceeded.into());
+        }
+        let added = |path: &str| {
+            edits
+                .iter()
+                .any(|edit| matches!(edit, DtbEdit::AddNode { path: p } if *p == path))
+        };
+        let resolve = |path: &'e str| -> Result<EditTarget<'e>, DtbError> {
+            if !path.starts_with('/') {
+                return Err(DtbErrorKind::NotFound.into());
+            }
+            let node = if added(path) {
+                // the node is only added when it does not exist with exactly this name
+                match self.find_node_by_exact_path(path)? {
+                    Some(node) => node,
+                    None => return Ok(EditTarget::Added(path)),
+                }
+            } else {
+                self.find_node_by_path(path)?
+                    .ok_or(DtbErrorKind::NotFound)?
+            };
+            Ok(EditTarget::Node(node.address))
+        };
+        for (i, edit) in edits.iter().enumerate() {
+            targets[i] = match *edit {
+                DtbEdit::SetProperty { path, .. } | DtbEdit::RemoveProperty { path, .. } => {
+                    resolve(path)?
+                }
+                DtbEdit::AddNode { path } => {
+                    let (parent, _) = split_parent(path).ok_or(DtbErrorKind::InvalidArgument)?;
+                    let parent = resolve(parent)?;
+                    let duplicate = edits[..i]
+                        .iter()
+                        .any(|edit| matches!(edit, DtbEdit::AddNode { path: p } if *p == path));
+                    if duplicate || self.find_node_by_exact_path(path)?.is_some() {
+                        EditTarget::None
+                    } else {
+                        parent
+                    }
+                }
+                DtbEdit::RemoveNode { path } => {
+                    if split_parent(path).is_none() {
+                        return Err(DtbErrorKind::InvalidArgument.into());
+                    }
+                    match self.find_node_by_path(path)? {
+                        Some(node) => EditTarget::Node(node.address),
+                        None => EditTarget::None,
+                    }
+                }
+                DtbEdit::AddReservation { .. } => EditTarget::None,
+            };
+        }
+        Ok(&targets[..edits.len()])
+    }
+
+    // writes the properties set by edits which the original node does not have
+    fn write_added_properties(
+        &self,
+        writer: &mut FdtWriter,
+        edits: &[DtbEdit],
+        targets: &[EditTarget],
+        node: EditTarget,
+    ) -> Result<(), DtbError> {
+        for (i, (edit, &target)) in edits.iter().zip(targets).enumerate() {
+            let DtbEdit::SetProperty { name, value, .. } = *edit else {
+                continue;
+            };
+            // only the last edit of the property is written
+            if target != node
+                || property_edit(&edits[i + 1..], &targets[i + 1..], node, name).is_some()
+            {
+                continue;
+            }
+            if let EditTarget::Node(address) = node
+                && DtbNode::new(self, address).property(name)?.is_some()
+            {
+                continue;
+            }
+            writer.property(name, value)?;
+        }
+        Ok(())
+    }
+
+    // writes the nodes added as children of `parent`, which is at `depth`
+    fn write_added_nodes(
+        &self,
+        writer: &mut FdtWriter,
+        edits: &[DtbEdit],
+        targets: &[EditTarget],
+        parent: EditTarget,
+        depth: usize,
+    ) -> Result<(), DtbError> {
+        for (edit, &target) in edits.iter().zip(targets) {
+            let DtbEdit::AddNode { path } = *edit else {
+                continue;
+            };
+            if target != parent {
+                continue;
+            }
+            if depth == DtbNode::MAX_DEPTH {
+                return Err(DtbErrorKind::TooDeep.into());
+            }
+            let (_, name) = split_parent(path).ok_or(DtbErrorKind::InvalidArgument)?;
+            let node = EditTarget::Added(path);
+            writer.begin_node(name)?;
+            self.write_added_properties(writer, edits, targets, node)?;
+            self.write_added_nodes(writer, edits, targets, node, depth + 1)?;
+            writer.end_node()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_modified_tree() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        // the tree is copied as it is without any edit
+        let mut buf = vec![0u8; test_data.len() + 0x1000];
+        let blob = parser.write_modified(&mut buf, &[]).unwrap();
+        assert!(blob.len() <= test_data.len());
+        let copied = unsafe { DtbParser::init(blob.as_ptr() as usize) }.unwrap();
+        let original_tree = crate::DeviceTree::new(&parser).unwrap();
+        let copied_tree = crate::DeviceTree::new(&copied).unwrap();
+        assert!(
+            original_tree
+                .nodes()
+                .zip(copied_tree.nodes())
+                .all(|(a, b)| {
+                    a.full_name() == b.full_name() && a.properties().eq(b.properties())
+                })
+        );
+        assert_eq!(original_tree.nodes().count(), copied_tree.nodes().count());
+
+        let initrd_start = 0x300_0000u64.to_be_bytes();
+        let initrd_end = 0x380_0000u64.to_be_bytes();
+        let edits = [
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "bootargs",
+                value: b"console=ttyAMA10,115200\0",
+            },
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "linux,initrd-start",
+                value: &initrd_start,
+            },
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "linux,initrd-end",
+                value: &initrd_end,
+            },
+            DtbEdit::AddNode {
+                path: "/chosen/bootloader",
+            },
+            DtbEdit::SetProperty {
+                path: "/chosen/bootloader",
+                name: "compatible",
+                value: b"rpi5-baremetal\0",
+            },
+            DtbEdit::RemoveProperty {
+                path: "/soc/serial@7d001000",
+                name: "status",
+            },
+            DtbEdit::RemoveNode {
+                path: "/axi/pcie@1000110000",
+            },
+            DtbEdit::AddReservation {
+                address: 0x20_0000,
+                size: 0x3e0_0000,
+            },
+        ];
+        let mut buf = vec![0u8; test_data.len() + 0x1000];
+        let blob = parser.write_modified(&mut buf, &edits).unwrap();
+        let modified = unsafe { DtbParser::init(blob.as_ptr() as usize) }.unwrap();
+
+        let chosen = modified.find_node_by_path("/chosen").unwrap().unwrap();
+        assert_eq!(
+            chosen.property("bootargs").unwrap().unwrap().as_str(),
+            Some("console=ttyAMA10,115200")
+        );
+        assert_eq!(
+            chosen
+                .property("linux,initrd-start")
+                .unwrap()
+                .unwrap()
+                .as_u64(),
+            Some(0x300_0000)
+        );
+        let bootloader = modified
+            .find_node_by_path("/chosen/bootloader")
+            .unwrap()
+            .unwrap();
+        assert!(bootloader.is_compatible("rpi5-baremetal").unwrap());
+        // the other properties are kept
+        assert_eq!(
+            modified.find_stdout_node().unwrap().unwrap().1,
+            Some("115200n8")
+        );
+
+        let uart = modified.find_node_by_alias("serial10").unwrap().unwrap();
+        assert!(uart.property("status").unwrap().is_none());
+        assert!(uart.property("compatible").unwrap().is_some());
+        assert!(
+            modified
+                .find_node_by_path("/axi/pcie@1000110000")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            modified
+                .find_node_by_path("/axi/pcie@1000120000/pci@0,0")
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(
+            modified.memory_reservations().collect::<Vec<_>>(),
+            [(0x0, 0x1000), (0x20_0000, 0x3e0_0000)]
+        );
+
+        // a property cannot be set on a node which does not exist
+        assert!(
+            parser
+                .write_modified(
+                    &mut buf,
+                    &[DtbEdit::RemoveProperty {
+                        path: "/unknown",
+                        name: "status"
+                    }]
+                )
+                .is_err()
+        );
+        assert!(parser.write_modified(&mut [0u8; 0x100], &[]).is_err());
+    }
+
+    #[test]
+    fn edits_apply_to_one_node() {
+        let mut buf = [0u8; 512];
+        let mut writer = FdtWriter::new(&mut buf).unwrap();
+        writer.begin_node("").unwrap();
+        writer.begin_node("soc").unwrap();
+        for name in ["serial@1000", "serial@2000"] {
+            writer.begin_node(name).unwrap();
+            writer.property_str("status", "disabled").unwrap();
+            writer.end_node().unwrap();
+        }
+        writer.end_node().unwrap();
+        writer.begin_node("chosen").unwrap();
+        writer.property_str("bootargs", "quiet").unwrap();
+        writer.end_node().unwrap();
+        writer.begin_node("node@1").unwrap();
+        writer.end_node().unwrap();
+        writer.end_node().unwrap();
+        let blob = writer.finish(0).unwrap();
+        let parser = DtbParser::from_bytes(blob).unwrap();
+
+        let edits = [
+            // the first serial node, as find_node_by_path finds it
+            DtbEdit::SetProperty {
+                path: "/soc/serial",
+                name: "status",
+                value: b"okay\0",
+            },
+            // node@1 is not the same node
+            DtbEdit::AddNode { path: "/node" },
+            DtbEdit::SetProperty {
+                path: "/node",
+                name: "added",
+                value: &[],
+            },
+            // the last edit of a property wins
+            DtbEdit::RemoveProperty {
+                path: "/chosen",
+                name: "bootargs",
+            },
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "bootargs",
+                value: b"console=ttyAMA10\0",
+            },
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "stdout-path",
+                value: b"serial10\0",
+            },
+            DtbEdit::RemoveProperty {
+                path: "/chosen",
+                name: "stdout-path",
+            },
+        ];
+        let mut buf = [0u8; 512];
+        let blob = parser.write_modified(&mut buf, &edits).unwrap();
+        let modified = DtbParser::from_bytes(blob).unwrap();
+
+        let status = |path| {
+            modified
+                .find_node_by_path(path)
+                .unwrap()
+                .unwrap()
+                .property("status")
+                .unwrap()
+                .unwrap()
+                .as_str()
+        };
+        assert_eq!(status("/soc/serial@1000"), Some("okay"));
+        assert_eq!(status("/soc/serial@2000"), Some("disabled"));
+
+        let root = modified.root().unwrap();
+        let added = super::super::overlay::child_by_name(root, "node")
+            .unwrap()
+            .unwrap();
+        assert!(added.property("added").unwrap().is_some());
+        let existing = modified.find_node_by_path("/node@1").unwrap().unwrap();
+        assert!(existing.property("added").unwrap().is_none());
+
+        let chosen = modified.find_node_by_path("/chosen").unwrap().unwrap();
+        assert_eq!(
+            chosen.property("bootargs").unwrap().unwrap().as_str(),
+            Some("console=ttyAMA10")
+        );
+        assert!(chosen.property("stdout-path").unwrap().is_none());
+
+        // a node which exists with exactly the same name is not added again
+        let blob = parser
+            .write_modified(&mut buf, &[DtbEdit::AddNode { path: "/node@1" }])
+            .unwrap();
+        let modified = DtbParser::from_bytes(blob).unwrap();
+        assert_eq!(modified.root().unwrap().children().count(), 3);
+    }
+}
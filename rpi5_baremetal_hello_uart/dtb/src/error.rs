@@ -0,0 +1,255 @@
+// error type of the dtb crate
+//
+// no allocation is done so that errors can be reported before any allocator is ready
+
+use core::fmt;
+
+/// category of a dtb error
+///
+/// early boot code matches on this to decide whether it can keep going
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DtbErrorKind {
+    /// the header does not start with 0xd00dfeed
+    InvalidMagic,
+    /// the blob is not compatible with the supported version
+    UnsupportedVersion,
+    /// a block, a string or a property ends outside of the blob
+    Truncated,
+    /// an unknown token or a token which is not allowed at that position
+    UnexpectedToken,
+    /// FDT_BEGIN_NODE and FDT_END_NODE do not match
+    UnbalancedNode,
+    /// a string is not null terminated or not valid UTF-8
+    InvalidString,
+    /// the value of a property does not have the expected form
+    InvalidProperty,
+    /// a value does not fit in usize (e.g. #address-cells is larger than 2 on aarch64)
+    Overflow,
+    /// an address is not covered by the 'ranges' of its parent bus
+    UntranslatableAddress,
+    /// no node has the phandle
+    UnknownPhandle,
+    /// interrupt-map has no entry for the interrupt
+    UnmappedInterrupt,
+    /// a node or a property which is required does not exist
+    NotFound,
+    /// the tree is deeper than the supported depth
+    TooDeep,
+    /// a fixed capacity container is full
+    CapacityExceeded,
+    /// the output buffer is too small
+    BufferTooSmall,
+    /// the caller passed an invalid argument or called a method in the wrong state
+    InvalidArgument,
+    /// an overlay fragment or fixup is broken
+    InvalidOverlay,
+    /// the rate of a clock is not described by the device tree
+    UnsupportedClock,
+}
+
+impl DtbErrorKind {
+    fn description(self) -> &'static str {
+        match self {
+            Self::InvalidMagic => "invalid magic",
+            Self::UnsupportedVersion => "unsupported dtb version",
+            Self::Truncated => "dtb is truncated",
+            Self::UnexpectedToken => "unexpected token",
+            Self::UnbalancedNode => "unbalanced node",
+            Self::InvalidString => "invalid string",
+            Self::InvalidProperty => "invalid property",
+            Self::Overflow => "value overflows usize",
+            Self::UntranslatableAddress => "address is not covered by ranges",
+            Self::UnknownPhandle => "unknown phandle",
+            Self::UnmappedInterrupt => "no entry in interrupt-map matched",
+            Self::NotFound => "not found",
+            Self::TooDeep => "device tree is too deep",
+            Self::CapacityExceeded => "capacity exceeded",
+            Self::BufferTooSmall => "buffer is too small",
+            Self::InvalidArgument => "invalid argument",
+            Self::InvalidOverlay => "invalid overlay",
+            Self::UnsupportedClock => "unsupported clock provider",
+        }
+    }
+}
+
+impl fmt::Display for DtbErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// an error with the location in the blob where it was found
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DtbError {
+    kind: DtbErrorKind,
+    offset: Option<usize>,
+    path: NodePath,
+}
+
+impl DtbError {
+    pub const fn new(kind: DtbErrorKind) -> Self {
+        Self {
+            kind,
+            offset: None,
+            path: NodePath::new(),
+        }
+    }
+
+    pub fn kind(&self) -> DtbErrorKind {
+        self.kind
+    }
+
+    /// byte offset from the start of the blob
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// path of the node, a long path only keeps its tail and starts with "..."
+    pub fn node_path(&self) -> Option<&str> {
+        self.path.as_str()
+    }
+
+    pub(crate) fn with_offset(mut self, offset: usize) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    // components are given from the node up to (but not including) the root
+    pub(crate) fn with_path<'a>(mut self, components: impl Iterator<Item = &'a str>) -> Self {
+        if self.path.is_empty() {
+            self.path.set(components);
+        }
+        self
+    }
+}
+
+impl From<DtbErrorKind> for DtbError {
+    fn from(kind: DtbErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl fmt::Display for DtbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {:#x}", offset)?;
+        }
+        if let Some(path) = self.node_path() {
+            write!(f, " in {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DtbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtbError")
+            .field("kind", &self.kind)
+            .field("offset", &self.offset)
+            .field("path", &self.node_path())
+            .finish()
+    }
+}
+
+impl core::error::Error for DtbError {}
+
+// node path stored inline, filled from its end because the components come from the leaf
+//
+// the capacity is kept small so that Result<_, DtbError> stays cheap to return
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct NodePath {
+    buf: [u8; Self::CAPACITY],
+    start: u8,
+}
+
+impl NodePath {
+    const CAPACITY: usize = 64;
+    const ELLIPSIS: &'static [u8] = b"...";
+
+    const fn new() -> Self {
+        Self {
+            buf: [0; Self::CAPACITY],
+            start: Self::CAPACITY as u8,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start as usize == Self::CAPACITY
+    }
+
+    fn set<'a>(&mut self, components: impl Iterator<Item = &'a str>) {
+        let mut empty = true;
+        for name in components {
+            empty = false;
+            if !self.prepend(name.as_bytes()) || !self.prepend(b"/") {
+                // prepend keeps room for the ellipsis
+                self.start -= Self::ELLIPSIS.len() as u8;
+                let start = self.start as usize;
+                self.buf[start..start + Self::ELLIPSIS.len()].copy_from_slice(Self::ELLIPSIS);
+                return;
+            }
+        }
+        if empty {
+            self.prepend(b"/");
+        }
+    }
+
+    fn prepend(&mut self, bytes: &[u8]) -> bool {
+        let Some(start) = (self.start as usize).checked_sub(Self::ELLIPSIS.len() + bytes.len())
+        else {
+            return false;
+        };
+        let start = start + Self::ELLIPSIS.len();
+        self.buf[start..start + bytes.len()].copy_from_slice(bytes);
+        self.start = start as u8;
+        true
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        if self.is_empty() {
+            return None;
+        }
+        core::str::from_utf8(&self.buf[self.start as usize..]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DtbParser;
+
+    #[test]
+    fn error_location() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = DtbParser::from_bytes(&test_data).unwrap();
+
+        let error = parser.root().unwrap().reg().err().unwrap();
+        assert_eq!(error.kind(), DtbErrorKind::InvalidProperty);
+        assert_eq!(error.node_path(), Some("/"));
+        let uart = parser.find_node_by_path("serial10").unwrap().unwrap();
+        let error = uart.error(DtbErrorKind::NotFound);
+        assert_eq!(error.node_path(), Some("/soc@107c000000/serial@7d001000"));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "not found at offset {:#x} in /soc@107c000000/serial@7d001000",
+                error.offset().unwrap()
+            )
+        );
+
+        // only the tail of a long path is kept
+        let error = DtbError::new(DtbErrorKind::TooDeep).with_path(["node@0"; 16].into_iter());
+        let path = error.node_path().unwrap();
+        assert!(path.starts_with("...") && path.ends_with("/node@0/node@0"));
+        assert!(path.len() <= NodePath::CAPACITY);
+
+        let mut bad_magic = test_data.clone();
+        bad_magic[0] = 0;
+        let error = DtbParser::from_bytes(&bad_magic).err().unwrap();
+        assert_eq!(error.kind(), DtbErrorKind::InvalidMagic);
+        assert_eq!(error.offset(), Some(0));
+        assert_eq!(error.node_path(), None);
+    }
+}
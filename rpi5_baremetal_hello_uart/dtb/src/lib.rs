@@ -1,387 +1,388 @@
 #![cfg_attr(not(test), no_std)]
 
-use core::{
-    ffi::{CStr, c_char},
-    ops::ControlFlow, usize,
-};
+use core::{ffi::CStr, mem::offset_of, ops::ControlFlow, slice};
 #[cfg(test)]
 use std::{collections::HashMap, string::String};
 
-#[cfg(alloc)]
-enum DeviceProperty {
-    String(&'static str),
-    StringList(Vec<&'static str>),
-    U32(u32),
-    Cells(Vec<u32>),
-    Empty,
-}
+#[cfg(any(test, feature = "alloc"))]
+extern crate alloc;
 
-#[cfg(alloc)]
-struct DeviceNode<'a> {
-    name: &'static [u8],
-    full_name: &'static [u8],
-    properties: HashMap<&'static [u8], DeviceProperty>,
-    parent: Option<NonNull<&'a DeviceNode<'a>>>,
-    child: Option<Vec<NonNull<&'a DeviceNode<'a>>>>,
-}
+#[cfg(any(test, feature = "alloc"))]
+mod device_tree;
+mod error;
+// the dts compiler which wrote the blobs in test/, compiled into the tests to check it
+#[cfg(test)]
+#[path = "../dtsc.rs"]
+mod dtsc;
 
-pub use dtb_parser::DtbParser;
+#[cfg(any(test, feature = "alloc"))]
+pub use device_tree::{DeviceNode, DeviceProperty, DeviceTree};
+pub use dtb_parser::{
+    Bias, Cells, ChildIter, Clock, ClockIter, Cpu, CpuIter, DtbEdit, DtbHeader, DtbNode, DtbParser,
+    DtbProperty, DtbVisitor, EnableMethod, FdtWriter, Interrupt, InterruptIter, InterruptSpecifier,
+    MemReserveIter, MemoryMap, PinConfig, PinctrlIter, PropertyIter, Psci, PsciMethod, RegIter,
+    ReservedMemory, ReservedMemoryIter, StrListIter,
+};
+pub use error::{DtbError, DtbErrorKind};
 
 mod dtb_parser {
     use super::*;
-    use big_endian::{CharStringIter, Dtb, FdtProperty};
-
-    struct SimpleDeviceNode<'a> {
-        parent: Option<&'a SimpleDeviceNode<'a>>,
-        address_cells: u32,
-        size_cells: u32,
-        reg: Option<(usize, u32)>,
-        ranges: Option<usize>,
+    use big_endian::{Dtb, FdtProperty, FdtReserveEntry};
+
+    mod clock;
+    mod cpu;
+    mod header;
+    mod interrupt;
+    mod memory;
+    mod node;
+    mod overlay;
+    mod pinctrl;
+    mod reserved;
+    mod visitor;
+    mod writer;
+    pub use clock::{Clock, ClockIter};
+    pub use cpu::{Cpu, CpuIter, EnableMethod, Psci, PsciMethod};
+    pub use header::DtbHeader;
+    pub use interrupt::{Interrupt, InterruptIter, InterruptSpecifier};
+    pub use memory::MemoryMap;
+    pub use node::{Cells, ChildIter, DtbNode, DtbProperty, PropertyIter, RegIter, StrListIter};
+    pub use pinctrl::{Bias, PinConfig, PinctrlIter};
+    pub use reserved::{MemReserveIter, ReservedMemory, ReservedMemoryIter};
+    pub use visitor::DtbVisitor;
+    pub use writer::{DtbEdit, FdtWriter};
+
+    /// a token of the structure block
+    pub(crate) enum StructToken<'a> {
+        // address points to the FDT_BEGIN_NODE token
+        BeginNode { address: usize },
+        Property { name: &'a str, value: &'a [u8] },
+        EndNode,
     }
 
-    impl<'a> SimpleDeviceNode<'a> {
-        const ADDRESS_CELLS: &'static str = "#address-cells";
-        const SIZE_CELLS: &'static str = "#size-cells";
-        const PROP_COMPATIBLE: &'static str = "compatible";
-        const PROP_DEVICE_NAME: &'static str = "device_type";
-        const PROP_REG: &'static str = "reg";
-        const PROP_RANGES: &'static str = "ranges";
+    // iterates over the structure block tokens, FDT_NOP is skipped and FDT_END finishes the iteration
+    pub(crate) struct StructTokenIter<'a> {
+        parser: &'a DtbParser<'a>,
+        pointer: usize,
+        finished: bool,
+    }
+
+    impl<'a> StructTokenIter<'a> {
+        pub(crate) fn new(parser: &'a DtbParser<'a>) -> Self {
+            Self::new_at(parser, parser.dtb_header.get_struct_start_address())
+        }
 
-        fn new<'b: 'a>(parent: Option<&'b SimpleDeviceNode>) -> Self {
+        // address is assumed to point to a token inside the structure block
+        pub(crate) fn new_at(parser: &'a DtbParser<'a>, address: usize) -> Self {
             Self {
-                address_cells: 2,
-                size_cells: 1,
-                reg: None,
-                ranges: None,
-                parent,
+                parser,
+                pointer: address,
+                finished: false,
             }
         }
 
-        // address is assumed to point to the FDT_PROP token
-        fn parse_prop(
-            &mut self,
-            parser: &DtbParser,
-            address: &mut usize,
-            device_name: Option<&str>,
-            compatible_name: Option<&str>,
-        ) -> Result<bool, &'static str> {
-            *address += DtbParser::SIZEOF_FDT_TOKEN;
-            let property = unsafe { &*(*address as *const FdtProperty) };
-            #[cfg(test)]
-            assert_eq!(size_of::<FdtProperty>(), 8);
-            *address += size_of::<FdtProperty>();
-            let name = Dtb::read_char_str(
-                parser.dtb_header.get_string_start_address() + property.get_name_offset() as usize,
-            )?;
-            pr_debug!(
-                "FDT_PROP offset: {}, str: {}",
-                property.get_name_offset(),
-                name
-            );
-            let mut result = false;
-            if let Some(s) = match name {
-                Self::ADDRESS_CELLS => {
-                    self.address_cells = Dtb::read_u32_from_ptr(*address);
-                    pr_debug!("address_cells: {}", self.address_cells);
-                    Some(size_of::<u32>())
+        fn next_internal(&mut self) -> Result<Option<StructToken<'a>>, DtbError> {
+            let header = &self.parser.dtb_header;
+            let end = header.get_struct_end_address();
+            self.parser.skip_nop(&mut self.pointer);
+            let address = self.pointer;
+            let token = self.parser.read_token(self.pointer)?;
+            self.pointer += DtbParser::SIZEOF_FDT_TOKEN;
+            match token {
+                DtbParser::FDT_BEGIN_NODE => {
+                    let name = header.read_char_str(self.pointer, end)?;
+                    self.pointer += (name.len() + 1/* null terminator */)
+                        .next_multiple_of(DtbParser::ALIGNMENT as usize);
+                    Ok(Some(StructToken::BeginNode { address }))
                 }
-                Self::SIZE_CELLS => {
-                    self.size_cells = Dtb::read_u32_from_ptr(*address);
-                    pr_debug!("size_cells: {}", self.size_cells);
-                    Some(size_of::<u32>())
+                DtbParser::FDT_PROP => {
+                    let property = FdtProperty::read(header, self.pointer, end)?;
+                    self.pointer += size_of::<FdtProperty>();
+                    let name = header.read_char_str(
+                        header.get_string_start_address() + property.get_name_offset() as usize,
+                        header.get_string_end_address(),
+                    )?;
+                    let value = header.read_bytes(
+                        self.pointer,
+                        property.get_property_len() as usize,
+                        end,
+                    )?;
+                    self.pointer += property
+                        .get_property_len()
+                        .next_multiple_of(DtbParser::ALIGNMENT)
+                        as usize;
+                    Ok(Some(StructToken::Property { name, value }))
                 }
-                Self::PROP_COMPATIBLE => {
-                    if let Some(compatible_name) = compatible_name {
-                        for str in CharStringIter::new(*address, property.get_property_len()) {
-                            if compatible_name == str? {
-                                result = true;
-                            }
-                        }
-                    }
-                    None
-                }
-                Self::PROP_DEVICE_NAME => {
-                    if let Some(device_name) = device_name
-                        && device_name == Dtb::read_char_str(*address)?
-                    {
-                        result = true;
-                    }
-                    None
-                }
-                Self::PROP_REG => {
-                    self.reg = Some((*address, property.get_property_len()));
-                    Some(
-                        self.parent
-                            .ok_or("'reg' property should not be located at the root node")
-                            .map(|node| {
-                                node.address_cells as usize * size_of::<u32>()
-                                    + node.size_cells as usize * size_of::<u32>()
-                            })?,
-                    )
-                }
-                Self::PROP_RANGES => {
-                    self.ranges = Some(*address);
-                    if property.get_property_len() != 0 {
-                        pr_debug!(
-                            "parent address: {}, child address: {}, child_size: {}",
-                            self.parent.unwrap().address_cells,
-                            self.address_cells,
-                            self.size_cells
-                        );
-                        None
-                    } else {
-                        Some(0)
-                    }
-                }
-                _ => None,
-            } {
-                if s > property.get_property_len() as usize {
-                    pr_debug!(
-                        "invalid size!!! expected: {} actually: {}",
-                        property.get_property_len(),
-                        s
-                    );
-                    return Err("invalid size");
+                DtbParser::FDT_END_NODE => Ok(Some(StructToken::EndNode)),
+                DtbParser::FDT_END => {
+                    self.finished = true;
+                    Ok(None)
                 }
+                _ => Err(DtbErrorKind::UnexpectedToken.into()),
             }
-            *address += property
-                .get_property_len()
-                .next_multiple_of(DtbParser::ALIGNMENT) as usize;
-            Ok(result)
         }
+    }
 
-        fn read_reg_internal(&self, offset: usize) -> Result<Option<(usize, usize)>, &'static str> {
-            if let Some(reg) = self.reg {
-                let (address_cells, size_cells) = self
-                    .parent
-                    .map(|node| (node.address_cells, node.size_cells))
-                    .unwrap();
-                if address_cells as usize > (size_of::<usize>() / size_of::<u32>())
-                    || size_cells as usize > (size_of::<usize>() / size_of::<u32>())
-                {
-                    return Err("address or size cells overflow usize");
-                }
-                let address = Dtb::read_regs(reg.0 + offset, address_cells)?;
-                let len = Dtb::read_regs(reg.0 + offset + address.1, size_cells)?;
-                pr_debug!("reg: address: {:#x}, size: {:#x}", address.0, len.0);
-                return Ok(Some((address.0, len.0)));
-            }
-            Ok(None)
-        }
+    impl<'a> Iterator for StructTokenIter<'a> {
+        type Item = Result<StructToken<'a>, DtbError>;
 
-        fn read_round_internal(&self) -> Result<Option<(usize, usize, usize)>, &'static str> {
-            if let Some(reg) = self.ranges {
-                let child_address = Dtb::read_regs(reg, self.address_cells)?;
-                let parent_address =
-                    Dtb::read_regs(reg + child_address.1, self.parent.unwrap().address_cells)?;
-                let parent_len =
-                    Dtb::read_regs(reg + child_address.1 + parent_address.1, self.size_cells)?;
-                #[cfg(test)]
-                assert_eq!(parent_len.1, self.size_cells as usize * size_of::<u32>());
-                return Ok(Some((child_address.0, parent_address.0, parent_len.0)));
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.finished {
+                return None;
             }
-            Ok(None)
-        }
-
-        fn calculate_address_internal(
-            &self,
-            address: &(usize, usize),
-        ) -> Result<(usize, usize), &'static str> {
-            let address_child = {
-                if self.ranges.is_some() {
-                    let parent = self.read_round_internal()?.unwrap();
-                    if parent.0 + parent.2 < address.0 + address.1 {
-                        return Err("ranges size overflow");
-                    }
-                    pr_debug!(
-                        "child_address: {:#x}, parent_address: {:#x}, child_len: {:#x}",
-                        parent.0,
-                        parent.1,
-                        parent.2
-                    );
-                    (address.0 - parent.0 + parent.1, address.1)
-                } else {
-                    *address
-                }
-            };
-            if let Some(s) = self.parent {
-                return s.calculate_address_internal(&address_child);
+            let address = self.pointer;
+            let result = self.next_internal();
+            if result.is_err() {
+                self.finished = true;
             }
-            return Ok(address_child);
+            result
+                .map_err(|e| e.with_offset(address - self.parser.dtb_header.get_address()))
+                .transpose()
         }
     }
 
-    struct DeviceAddressIter<'a> {
-        prop: &'a SimpleDeviceNode<'a>,
-        remain: Option<u32>,
+    pub struct DtbParser<'dtb> {
+        dtb_header: Dtb<'dtb>,
     }
 
-    impl<'a> DeviceAddressIter<'a> {
-        fn new(prop: &'a SimpleDeviceNode) -> Self {
-            Self { prop, remain: None }
-        }
-        fn next_internal(&mut self) -> Result<Option<(usize, usize)>, &'static str> {
-            let (_address, size) = self.prop.reg.ok_or("reg property is none")?;
-            let remain = self.remain.get_or_insert(size);
-            if *remain == 0 {
-                return Ok(None);
-            }
-            pr_debug!("read reg offset at: {}", size - *remain);
-            let result = self.prop.calculate_address_internal(
-                &self
-                    .prop
-                    .read_reg_internal(size as usize - *remain as usize)?
-                    .ok_or("reg property is none")?,
-            )?;
-            *remain -= self
-                .prop
-                .parent
-                .map(|parent| (parent.address_cells + parent.size_cells) * size_of::<u32>() as u32)
-                .unwrap();
-            return Ok(Some(result));
+    impl<'dtb> DtbParser<'dtb> {
+        const SIZEOF_FDT_TOKEN: usize = 4;
+        const ALIGNMENT: u32 = 4;
+        const FDT_BEGIN_NODE: [u8; DtbParser::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x01];
+        const FDT_END_NODE: [u8; DtbParser::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x02];
+        const FDT_PROP: [u8; DtbParser::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x03];
+        const FDT_NOP: [u8; DtbParser::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x04];
+        const FDT_END: [u8; DtbParser::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x09];
+        const NODE_ALIASES: &'static str = "aliases";
+        const NODE_CHOSEN: &'static str = "chosen";
+        const PROP_STDOUT_PATH: &'static str = "stdout-path";
+        const PROP_LINUX_STDOUT_PATH: &'static str = "linux,stdout-path";
+        /// parses a dtb at a raw address, e.g. where the firmware has placed it
+        ///
+        /// # Safety
+        ///
+        /// same as from_ptr: the memory from `dtb_address` must be readable for the 'totalsize'
+        /// in its header and stay unchanged while the parser is used
+        pub unsafe fn init(dtb_address: usize) -> Result<DtbParser<'static>, DtbError> {
+            unsafe { DtbParser::from_ptr(dtb_address as *const u8) }
         }
-    }
 
-    impl<'a> Iterator for DeviceAddressIter<'a> {
-        type Item = Result<(usize, usize), &'static str>;
-
-        fn next(&mut self) -> Option<Self::Item> {
-            self.next_internal().transpose()
+        /// parses a dtb in a slice, nothing outside of `blob` is read
+        ///
+        /// `blob` may be longer than 'totalsize' (e.g. a whole partition read from a disk)
+        pub fn from_bytes(blob: &'dtb [u8]) -> Result<Self, DtbError> {
+            let dtb = Dtb::new(blob)?;
+            Ok(Self { dtb_header: dtb })
         }
-    }
 
-    pub struct DtbParser {
-        dtb_header: Dtb,
-    }
+        /// parses a dtb whose length is only known from its header
+        ///
+        /// # Safety
+        ///
+        /// `address` must be readable for the magic and 'totalsize' and then for the 'totalsize',
+        /// and the memory must stay unchanged while the parser is used
+        pub unsafe fn from_ptr(address: *const u8) -> Result<DtbParser<'static>, DtbError> {
+            let header = unsafe { slice::from_raw_parts(address, Dtb::size_of_total_size()) };
+            let total_size = Dtb::read_total_size(header)?;
+            DtbParser::from_bytes(unsafe { slice::from_raw_parts(address, total_size) })
+        }
 
-    impl DtbParser {
-        const SIZEOF_FDT_TOKEN: usize = 4;
-        const ALIGNMENT: u32 = 4;
-        const FDT_BEGIN_NODE: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x01];
-        const FDT_END_NODE: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x02];
-        const FDT_PROP: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x03];
-        const FDT_NOP: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x04];
-        const FDT_END: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x09];
-        pub fn init(dtb_address: usize) -> Result<Self, &'static str> {
-            let dtb = Dtb::new(dtb_address)?;
-            let parser = Self { dtb_header: dtb };
-            return Ok(parser);
+        // error located at `address` inside the blob
+        pub(crate) fn error_at(&self, kind: DtbErrorKind, address: usize) -> DtbError {
+            DtbError::new(kind).with_offset(address - self.dtb_header.get_address())
         }
         fn skip_nop(&self, address: &mut usize) {
-            while *address < self.dtb_header.get_struct_end_address()
-                && Self::get_types(address) == Self::FDT_NOP
-            {
+            while self.read_token(*address) == Ok(Self::FDT_NOP) {
                 *address += Self::SIZEOF_FDT_TOKEN;
             }
         }
 
-        fn get_types(address: &usize) -> [u8; 4] {
-            unsafe { *(*address as *const [u8; Self::SIZEOF_FDT_TOKEN]) }
+        // the structure block ends with FDT_END, so running out of it means the blob is truncated
+        fn read_token(
+            &self,
+            address: usize,
+        ) -> Result<[u8; DtbParser::SIZEOF_FDT_TOKEN], DtbError> {
+            let token = self.dtb_header.read_bytes(
+                address,
+                Self::SIZEOF_FDT_TOKEN,
+                self.dtb_header.get_struct_end_address(),
+            )?;
+            Ok(token.try_into().unwrap())
         }
 
+        /// calls `f` with every 'reg' entry of the matching nodes, translated into the CPU physical
+        /// address space
+        ///
+        /// a matching node without 'reg' is an error
         pub fn find_node<F>(
             &self,
             device_name: Option<&str>,
             compatible_name: Option<&str>,
             f: &mut F,
-        ) -> Result<(), &'static str>
+        ) -> Result<(), DtbError>
         where
             F: FnMut((usize, usize)) -> ControlFlow<()>,
         {
-            if device_name.is_some() && compatible_name.is_some()
-                || device_name.is_none() && compatible_name.is_none()
-            {
-                return Err(
-                    "device name and compatible name cannot be searched for at the same time",
-                );
-            }
-            let mut pointer = self.dtb_header.get_struct_start_address();
-            self.skip_nop(&mut pointer);
-            let _result =
-                self.find_node_recursive(&mut pointer, device_name, compatible_name, None, f)?;
-            if Self::get_types(&pointer) != Self::FDT_END {
-                pr_debug!(
-                    "failed to parse all of the dtb node: {:?}",
-                    Self::get_types(&pointer)
-                );
-                return Err("failed to parse all of the dtb node");
+            let mut result = Ok(());
+            self.find_nodes(device_name, compatible_name, &mut |node| {
+                let reg = match node.property(DtbNode::PROP_REG) {
+                    Ok(Some(_)) => node.reg(),
+                    Ok(None) => Err(node.error(DtbErrorKind::NotFound)),
+                    Err(e) => Err(e),
+                };
+                let walk = || {
+                    for address in reg? {
+                        if f(address?).is_break() {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                    }
+                    Ok(ControlFlow::Continue(()))
+                };
+                walk().unwrap_or_else(|e| {
+                    result = Err(e);
+                    ControlFlow::Break(())
+                })
+            })?;
+            result
+        }
+
+        pub fn root(&self) -> Result<DtbNode<'_>, DtbError> {
+            match StructTokenIter::new(self).next() {
+                Some(Ok(StructToken::BeginNode { address, .. })) => Ok(DtbNode::new(self, address)),
+                Some(Err(e)) => Err(e),
+                _ => Err(DtbErrorKind::UnexpectedToken.into()),
             }
-            return Ok(());
         }
 
-        fn find_node_recursive<F>(
+        /// same as find_node, but passes a handle of the found node instead of its 'reg'
+        ///
+        /// when both names are given, only nodes matching both of them are found
+        pub fn find_nodes<F>(
             &self,
-            pointer: &mut usize,
             device_name: Option<&str>,
             compatible_name: Option<&str>,
-            node_info: Option<&SimpleDeviceNode>,
             f: &mut F,
-        ) -> Result<ControlFlow<()>, &'static str>
+        ) -> Result<(), DtbError>
         where
-            F: FnMut((usize, usize)) -> ControlFlow<()>,
+            F: FnMut(DtbNode) -> ControlFlow<()>,
         {
-            if Self::get_types(&pointer) != Self::FDT_BEGIN_NODE {
-                return Err("pointer is not begin node");
-            }
-            let mut prop = SimpleDeviceNode::new(node_info);
-            *pointer += Self::SIZEOF_FDT_TOKEN;
-            let node_name = Dtb::read_char_str(*pointer)?;
-            *pointer += (node_name.len() + 1/* null terminator */)
-                .next_multiple_of(Self::ALIGNMENT as usize);
-            pr_debug!("node name: {}", node_name);
-            let mut find_in_this_node = false;
-            loop {
-                match Self::get_types(pointer) {
-                    Self::FDT_NOP => *pointer += Self::SIZEOF_FDT_TOKEN,
-                    Self::FDT_PROP => {
-                        if prop.parse_prop(self, pointer, device_name, compatible_name)? {
-                            find_in_this_node = true;
-                        }
-                    }
-                    Self::FDT_BEGIN_NODE | Self::FDT_END_NODE => break,
-                    _ => return Err("Unexpected token inside node"),
+            if device_name.is_none() && compatible_name.is_none() {
+                return Err(DtbErrorKind::InvalidArgument.into());
+            }
+            for token in StructTokenIter::new(self) {
+                let StructToken::BeginNode { address, .. } = token? else {
+                    continue;
+                };
+                let node = DtbNode::new(self, address);
+                let mut found = true;
+                if let Some(device_name) = device_name {
+                    found &= node
+                        .property(DtbNode::PROP_DEVICE_TYPE)?
+                        .and_then(|p| p.as_str())
+                        == Some(device_name);
+                }
+                if let Some(compatible_name) = compatible_name {
+                    found = found && node.is_compatible(compatible_name)?;
+                }
+                if found && f(node).is_break() {
+                    break;
                 }
             }
+            Ok(())
+        }
 
-            if find_in_this_node {
-                for address in DeviceAddressIter::new(&prop) {
-                    if f(address?).is_break() {
-                        return Ok(ControlFlow::Break(()));
-                    }
+        /// finds the enabled nodes compatible with one of `compatibles`, like of_match in Linux
+        ///
+        /// `f` also receives the index of the matched entry, see DtbNode::match_compatible.
+        /// nodes whose 'status' is not "okay" are skipped
+        pub fn find_compatible_nodes<'a, F>(
+            &'a self,
+            compatibles: &[&str],
+            f: &mut F,
+        ) -> Result<(), DtbError>
+        where
+            F: FnMut(DtbNode<'a>, usize) -> ControlFlow<()>,
+        {
+            if compatibles.is_empty() {
+                return Err(DtbErrorKind::InvalidArgument.into());
+            }
+            for token in StructTokenIter::new(self) {
+                let StructToken::BeginNode { address, .. } = token? else {
+                    continue;
+                };
+                let node = DtbNode::new(self, address);
+                let Some(index) = node.match_compatible(compatibles)? else {
+                    continue;
+                };
+                if node.is_enabled()? && f(node, index).is_break() {
+                    break;
                 }
             }
+            Ok(())
+        }
 
-            loop {
-                match Self::get_types(pointer) {
-                    Self::FDT_NOP => *pointer += Self::SIZEOF_FDT_TOKEN,
-                    Self::FDT_BEGIN_NODE => {
-                        if self
-                            .find_node_recursive(
-                                pointer,
-                                device_name,
-                                compatible_name,
-                                Some(&prop),
-                                f,
-                            )?
-                            .is_break()
-                        {
-                            return Ok(ControlFlow::Break(()));
-                        }
-                    }
-                    Self::FDT_END_NODE => {
-                        *pointer += Self::SIZEOF_FDT_TOKEN;
-                        return Ok(ControlFlow::Continue(()));
-                    }
-                    _ => {
-                        pr_debug!(
-                            "find an unknown or unexpected token: {:?}, address: 0x{:#?}",
-                            Self::get_types(pointer),
-                            *pointer - self.dtb_header.get_struct_start_address()
-                        );
-                        return Err("find an unknown or unexpected token while parsing the DTB");
+        /// finds a node by its path
+        ///
+        /// an absolute path starts with '/', otherwise the first component is looked up in /aliases.
+        /// the unit address can be omitted when it is unambiguous, and options after ':' are ignored
+        pub fn find_node_by_path(&self, path: &str) -> Result<Option<DtbNode<'_>>, DtbError> {
+            let path = path.split(':').next().unwrap_or(path);
+            let (mut node, rest) = match path.strip_prefix('/') {
+                Some(rest) => (self.root()?, rest),
+                None => {
+                    let (alias, rest) = path.split_once('/').unwrap_or((path, ""));
+                    match self.find_node_by_alias(alias)? {
+                        Some(node) => (node, rest),
+                        None => return Ok(None),
                     }
                 }
+            };
+            for component in rest.split('/').filter(|c| !c.is_empty()) {
+                match node.find_child(component)? {
+                    Some(child) => node = child,
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(node))
+        }
+
+        /// resolves an entry of the /aliases node
+        pub fn find_node_by_alias(&self, alias: &str) -> Result<Option<DtbNode<'_>>, DtbError> {
+            let Some(aliases) = self.root()?.find_child(Self::NODE_ALIASES)? else {
+                return Ok(None);
+            };
+            let Some(path) = aliases.property(alias)? else {
+                return Ok(None);
+            };
+            let path = path
+                .as_str()
+                .ok_or_else(|| aliases.error(DtbErrorKind::InvalidProperty))?;
+            if !path.starts_with('/') {
+                return Err(aliases.error(DtbErrorKind::InvalidProperty));
             }
+            self.find_node_by_path(path)
+        }
+
+        /// finds the console specified by 'stdout-path' of /chosen
+        ///
+        /// returns the node and the options after ':' (e.g. "115200n8")
+        pub fn find_stdout_node(&self) -> Result<Option<(DtbNode<'_>, Option<&str>)>, DtbError> {
+            let Some(chosen) = self.root()?.find_child(Self::NODE_CHOSEN)? else {
+                return Ok(None);
+            };
+            let Some(stdout) = (match chosen.property(Self::PROP_STDOUT_PATH)? {
+                Some(property) => Some(property),
+                None => chosen.property(Self::PROP_LINUX_STDOUT_PATH)?,
+            }) else {
+                return Ok(None);
+            };
+            let stdout = stdout
+                .as_str()
+                .ok_or_else(|| chosen.error(DtbErrorKind::InvalidProperty))?;
+            let (path, options) = match stdout.split_once(':') {
+                Some((path, options)) => (path, Some(options)),
+                None => (stdout, None),
+            };
+            Ok(self.find_node_by_path(path)?.map(|node| (node, options)))
         }
     }
 
@@ -409,11 +410,19 @@ mod dtb_parser {
         }
 
         impl FdtProperty {
+            // address is assumed to point right after the FDT_PROP token
+            pub fn read(dtb: &Dtb<'_>, address: usize, end: usize) -> Result<Self, DtbError> {
+                let bytes = dtb.read_bytes(address, size_of::<Self>(), end)?;
+                Ok(Self {
+                    property_len: Dtb::read_u32(bytes, 0),
+                    name_offset: Dtb::read_u32(bytes, 4),
+                })
+            }
             pub fn get_property_len(&self) -> u32 {
-                u32::from_be(self.property_len)
+                self.property_len
             }
             pub fn get_name_offset(&self) -> u32 {
-                u32::from_be(self.name_offset)
+                self.name_offset
             }
         }
 
@@ -424,111 +433,198 @@ mod dtb_parser {
         }
 
         impl FdtReserveEntry {
+            pub fn read(bytes: &[u8; size_of::<Self>()]) -> Self {
+                let (address, size) = bytes.split_at(size_of::<u64>());
+                Self {
+                    address: u64::from_be_bytes(address.try_into().unwrap()),
+                    size: u64::from_be_bytes(size.try_into().unwrap()),
+                }
+            }
             pub fn get_address(&self) -> u64 {
-                u64::from_be(self.address)
+                self.address
             }
             pub fn get_size(&self) -> u64 {
-                u64::from_be(self.size)
+                self.size
             }
         }
 
-        pub struct Dtb {
-            address: &'static FtdHeader,
+        // every block in the header is checked to be inside of `blob` when this is created
+        pub struct Dtb<'dtb> {
+            blob: &'dtb [u8],
+            // size_dt_struct does not exist before version 17
+            struct_size: usize,
         }
 
-        impl Dtb {
-            const DTB_VERSION: u32 = 17;
-            const DTB_HEADER_MAGIC: u32 = 0xd00d_feed;
-            pub fn new(address: usize) -> Result<Dtb, &'static str> {
-                let ftb = Self {
-                    address: unsafe { &*(address as *const FtdHeader) },
+        impl<'dtb> Dtb<'dtb> {
+            pub const DTB_VERSION: u32 = 17;
+            // the oldest version whose structure block has the same layout (unit names only)
+            pub const DTB_OLDEST_VERSION: u32 = 16;
+            pub const DTB_HEADER_MAGIC: u32 = 0xd00d_feed;
+            pub const HEADER_SIZE: usize = size_of::<FtdHeader>();
+            // the header of version 16 ends before size_dt_struct
+            const HEADER_SIZE_V16: usize = offset_of!(FtdHeader, size_dt_struct);
+            pub fn new(blob: &'dtb [u8]) -> Result<Self, DtbError> {
+                let total_size = Self::read_total_size(blob)?;
+                if total_size > blob.len() {
+                    return Err(DtbError::new(DtbErrorKind::Truncated)
+                        .with_offset(offset_of!(FtdHeader, total_size)));
+                }
+                let mut ftb = Self {
+                    blob: &blob[..total_size],
+                    struct_size: 0,
+                };
+                let version = ftb.header_field(offset_of!(FtdHeader, version));
+                pr_debug!(
+                    "dtb version: {}, compatible with: {}",
+                    version,
+                    ftb.header_field(offset_of!(FtdHeader, last_comp_version))
+                );
+                // a newer blob can be read as long as it is compatible with the version 17
+                if ftb.header_field(offset_of!(FtdHeader, last_comp_version)) > Self::DTB_VERSION {
+                    return Err(DtbError::new(DtbErrorKind::UnsupportedVersion)
+                        .with_offset(offset_of!(FtdHeader, last_comp_version)));
+                }
+                if version < Self::DTB_OLDEST_VERSION {
+                    return Err(DtbError::new(DtbErrorKind::UnsupportedVersion)
+                        .with_offset(offset_of!(FtdHeader, version)));
+                }
+                let header_size = if version >= Self::DTB_VERSION {
+                    Self::HEADER_SIZE
+                } else {
+                    Self::HEADER_SIZE_V16
                 };
-                let header = ftb.address;
-                pr_debug!("dtb version: {}", u32::from_be(header.last_comp_version));
-                if u32::from_be(header.magic) != Self::DTB_HEADER_MAGIC {
-                    return Err("invalid magic");
+                if total_size < header_size {
+                    return Err(DtbError::new(DtbErrorKind::Truncated)
+                        .with_offset(offset_of!(FtdHeader, total_size)));
                 }
-                if u32::from_be(header.last_comp_version) > Self::DTB_VERSION {
-                    return Err("this dtb is not compatible with the version 17");
+                let struct_start = ftb.header_field(offset_of!(FtdHeader, off_dt_struct)) as usize;
+                let strings_start =
+                    ftb.header_field(offset_of!(FtdHeader, off_dt_strings)) as usize;
+                ftb.struct_size = if version >= Self::DTB_VERSION {
+                    ftb.header_field(offset_of!(FtdHeader, size_dt_struct)) as usize
+                } else if struct_start < strings_start {
+                    // the structure block ends with FDT_END, it is limited by the next block
+                    strings_start - struct_start
+                } else {
+                    total_size.saturating_sub(struct_start)
+                };
+                let blocks = [
+                    (offset_of!(FtdHeader, off_dt_struct), ftb.struct_size),
+                    (
+                        offset_of!(FtdHeader, off_dt_strings),
+                        ftb.header_field(offset_of!(FtdHeader, size_dt_strings)) as usize,
+                    ),
+                    // the memory reservation block is terminated by an empty entry
+                    (offset_of!(FtdHeader, off_mem_rsvmap), 0),
+                ];
+                for (offset, size) in blocks {
+                    let start = ftb.header_field(offset) as usize;
+                    if start < header_size || start + size > total_size {
+                        return Err(DtbError::new(DtbErrorKind::Truncated).with_offset(offset));
+                    }
                 }
                 Ok(ftb)
             }
+            // checks the magic and returns 'totalsize'
+            // only the magic and 'totalsize' are read, so `blob` can be shorter than 'totalsize'
+            pub fn read_total_size(blob: &[u8]) -> Result<usize, DtbError> {
+                let field = |offset| {
+                    blob.get(offset..offset + size_of::<u32>())
+                        .map(|bytes| Self::read_u32(bytes, 0))
+                        .ok_or(DtbError::new(DtbErrorKind::Truncated).with_offset(offset))
+                };
+                if field(offset_of!(FtdHeader, magic))? != Self::DTB_HEADER_MAGIC {
+                    return Err(DtbError::new(DtbErrorKind::InvalidMagic)
+                        .with_offset(offset_of!(FtdHeader, magic)));
+                }
+                let total_size = field(offset_of!(FtdHeader, total_size))? as usize;
+                if total_size < Self::HEADER_SIZE_V16 {
+                    return Err(DtbError::new(DtbErrorKind::Truncated)
+                        .with_offset(offset_of!(FtdHeader, total_size)));
+                }
+                Ok(total_size)
+            }
+            // the prefix of the header which is needed to know 'totalsize'
+            pub const fn size_of_total_size() -> usize {
+                offset_of!(FtdHeader, total_size) + size_of::<u32>()
+            }
+            pub fn header(&self) -> DtbHeader {
+                let version = self.header_field(offset_of!(FtdHeader, version));
+                DtbHeader {
+                    total_size: self.header_field(offset_of!(FtdHeader, total_size)),
+                    off_dt_struct: self.header_field(offset_of!(FtdHeader, off_dt_struct)),
+                    off_dt_strings: self.header_field(offset_of!(FtdHeader, off_dt_strings)),
+                    off_mem_rsvmap: self.header_field(offset_of!(FtdHeader, off_mem_rsvmap)),
+                    version,
+                    last_comp_version: self.header_field(offset_of!(FtdHeader, last_comp_version)),
+                    boot_cpuid_phys: self.header_field(offset_of!(FtdHeader, boot_cpuid_phys)),
+                    size_dt_strings: self.header_field(offset_of!(FtdHeader, size_dt_strings)),
+                    size_dt_struct: (version >= Self::DTB_VERSION)
+                        .then(|| self.header_field(offset_of!(FtdHeader, size_dt_struct))),
+                }
+            }
+            fn header_field(&self, offset: usize) -> u32 {
+                Self::read_u32(self.blob, offset)
+            }
+            pub fn get_address(&self) -> usize {
+                self.blob.as_ptr() as usize
+            }
+            pub fn get_total_size(&self) -> usize {
+                self.blob.len()
+            }
             pub fn get_struct_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_dt_struct) as usize
+                self.get_address()
+                    + self.header_field(offset_of!(FtdHeader, off_dt_struct)) as usize
             }
             pub fn get_struct_end_address(&self) -> usize {
-                self.get_struct_start_address() + u32::from_be(self.address.size_dt_struct) as usize
+                self.get_struct_start_address() + self.struct_size
             }
             pub fn get_string_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_dt_strings) as usize
+                self.get_address()
+                    + self.header_field(offset_of!(FtdHeader, off_dt_strings)) as usize
             }
             pub fn get_string_end_address(&self) -> usize {
                 self.get_string_start_address()
-                    + u32::from_be(self.address.size_dt_strings) as usize
-            }
-            pub fn get_memory_reservation_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_mem_rsvmap) as usize
-            }
-            pub fn read_u32_from_ptr(address: usize) -> u32 {
-                u32::from_be(unsafe { *(address as *const u32) })
-            }
-            // reads null-terminated strings and covert to &str
-            pub fn read_char_str(address: usize) -> Result<&'static str, &'static str> {
-                let str = unsafe { CStr::from_ptr(address as *const c_char) };
-                Ok(str
-                    .to_str()
-                    .map_err(|_| "failed to convert &Cstr to &str")?)
-            }
-            // read 'reg' property value
-            pub fn read_regs(address: usize, size: u32) -> Result<(usize, usize), &'static str> {
-                let mut address_result = 0;
-                let mut address_consumed = 0;
-                pr_debug!("read_reg: {}", size);
-                for _ in 0..size {
-                    address_result <<= 32;
-                    address_result +=
-                        u32::from_be(unsafe { *((address + address_consumed) as *const u32) })
-                            as usize;
-                    address_consumed += size_of::<u32>();
-                }
-                Ok((address_result, address_consumed))
+                    + self.header_field(offset_of!(FtdHeader, size_dt_strings)) as usize
             }
-        }
-        // an iterator over a list of null terminated strings within a property
-        pub struct CharStringIter {
-            pointer: usize,
-            remain_size: u32,
-        }
-        impl CharStringIter {
-            pub fn new(pointer: usize, remain_size: u32) -> Self {
-                Self {
-                    pointer,
-                    remain_size,
-                }
+            pub fn get_boot_cpuid_phys(&self) -> u32 {
+                self.header_field(offset_of!(FtdHeader, boot_cpuid_phys))
             }
-            fn next_internal(&mut self) -> Result<&'static str, &'static str> {
-                let str = Dtb::read_char_str(self.pointer);
-                if let Ok(s) = str {
-                    let str_size = s.len() + 1 /* null terminator */;
-                    self.pointer += str_size;
-                    self.remain_size = self
-                        .remain_size
-                        .checked_sub(str_size.try_into().map_err(|_| "over flow")?)
-                        .ok_or("too small")?;
-                }
-                str
+            // from the memory reservation block to the end of the blob
+            pub fn get_memory_reservation_block(&self) -> &'dtb [u8] {
+                &self.blob[self.header_field(offset_of!(FtdHeader, off_mem_rsvmap)) as usize..]
             }
-        }
-        impl Iterator for CharStringIter {
-            type Item = Result<&'static str, &'static str>;
-            fn next(&mut self) -> Option<Self::Item> {
-                if self.remain_size == 0 {
-                    return None;
+            // `offset` is assumed to be inside of `bytes`
+            pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+                u32::from_be_bytes(bytes[offset..offset + size_of::<u32>()].try_into().unwrap())
+            }
+            // reads `len` bytes at `address`, which must end before `end`
+            pub fn read_bytes(
+                &self,
+                address: usize,
+                len: usize,
+                end: usize,
+            ) -> Result<&'dtb [u8], DtbError> {
+                let start = self.get_address();
+                let offset = address.wrapping_sub(start);
+                if address < start
+                    || end > start + self.blob.len()
+                    || address.checked_add(len).is_none_or(|last| last > end)
+                {
+                    return Err(DtbError::new(DtbErrorKind::Truncated).with_offset(offset));
                 }
-                Some(self.next_internal())
+                Ok(&self.blob[offset..offset + len])
+            }
+            // reads a null-terminated string at `address`, the terminator must be before `end`
+            pub fn read_char_str(&self, address: usize, end: usize) -> Result<&'dtb str, DtbError> {
+                let bytes = self.read_bytes(address, end.saturating_sub(address), end)?;
+                let str = CStr::from_bytes_until_nul(bytes).map_err(|_| {
+                    DtbError::new(DtbErrorKind::Truncated).with_offset(address - self.get_address())
+                })?;
+                str.to_str().map_err(|_| {
+                    DtbError::new(DtbErrorKind::InvalidString)
+                        .with_offset(address - self.get_address())
+                })
             }
         }
     }
@@ -546,9 +642,9 @@ mod tests {
     const MEMORY_SIZE: usize = 0x2800_0000;
     #[test]
     fn it_works() {
-        let test_data = std::fs::read("test/test.dtb").expect("failed to load dtb files");
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
         let test_data_addr = test_data.as_ptr() as usize;
-        let parser = DtbParser::init(test_data_addr).unwrap();
+        let parser = unsafe { DtbParser::init(test_data_addr) }.unwrap();
 
         let mut counter = 0;
         parser
@@ -583,6 +679,359 @@ mod tests {
             .unwrap();
         assert_eq!(counter, 4);
     }
+
+    #[test]
+    fn find_node_by_path_and_alias() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        fn node_name(node: Option<DtbNode<'_>>) -> Option<&str> {
+            node.map(|node| node.name().unwrap())
+        }
+        assert_eq!(
+            node_name(parser.find_node_by_path("/soc/serial@7d001000").unwrap()),
+            Some("serial@7d001000")
+        );
+        assert_eq!(
+            node_name(
+                parser
+                    .find_node_by_path("/soc@107c000000/serial@7d001000")
+                    .unwrap()
+            ),
+            Some("serial@7d001000")
+        );
+        assert_eq!(node_name(parser.find_node_by_path("/").unwrap()), Some(""));
+        assert!(
+            parser
+                .find_node_by_path("/soc/serial@7d001001")
+                .unwrap()
+                .is_none()
+        );
+
+        assert_eq!(
+            node_name(parser.find_node_by_alias("serial10").unwrap()),
+            Some("serial@7d001000")
+        );
+        assert_eq!(
+            node_name(parser.find_node_by_alias("uart0").unwrap()),
+            Some("serial@c040030000")
+        );
+        assert!(parser.find_node_by_alias("serial99").unwrap().is_none());
+        assert_eq!(
+            node_name(parser.find_node_by_path("gpio0/rp1_uart0_14_15").unwrap()),
+            Some("rp1_uart0_14_15")
+        );
+
+        let (stdout, options) = parser.find_stdout_node().unwrap().unwrap();
+        assert_eq!(stdout.name().unwrap(), "serial@7d001000");
+        assert_eq!(options, Some("115200n8"));
+
+        let mut counter = 0;
+        parser
+            .find_node(Some("cpu"), Some("arm,cortex-a76"), &mut |_| {
+                counter += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 4);
+        assert!(
+            parser
+                .find_node(None, None, &mut |_| ControlFlow::Continue(()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn find_compatible_nodes() {
+        let test_data = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+        let parser = unsafe { DtbParser::init(test_data.as_ptr() as usize) }.unwrap();
+
+        let mut found = Vec::new();
+        parser
+            .find_compatible_nodes(&["arm,pl011-axi", "arm,pl011"], &mut |node, index| {
+                found.push((node.name().unwrap(), index));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        // serial@c040034000 is disabled
+        assert_eq!(found, [("serial@7d001000", 1), ("serial@c040030000", 0)]);
+
+        // the order of the table wins over the order in the node
+        let uart = parser.find_node_by_alias("serial10").unwrap().unwrap();
+        assert_eq!(
+            uart.match_compatible(&["arm,primecell", "arm,pl011"]),
+            Ok(Some(0))
+        );
+        assert_eq!(uart.match_compatible(&["brcm,bcm2712-uart"]), Ok(None));
+        let pcie1 = parser
+            .find_node_by_path("/axi/pcie@1000110000")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pcie1.is_enabled(), Ok(false));
+        assert_eq!(parser.root().unwrap().is_enabled(), Ok(true));
+        assert!(
+            parser
+                .find_compatible_nodes(&[], &mut |_, _| ControlFlow::Continue(()))
+                .is_err()
+        );
+    }
+
+    // walks every API over the blob, errors are fine but nothing may panic or read outside of it
+    fn walk_malformed(blob: Vec<u8>) {
+        let Ok(parser) = DtbParser::from_bytes(&blob) else {
+            return;
+        };
+        let _ = parser.find_node(None, Some("arm,pl011"), &mut |_| ControlFlow::Continue(()));
+        let _ = parser.find_node(Some("memory"), None, &mut |_| ControlFlow::Continue(()));
+        let _ = parser.find_stdout_node();
+        let _ = parser.usable_memory::<16>(&[]);
+        let _ = parser.memory_reservations().count();
+        let _ = parser.cpus().map(|cpus| cpus.count());
+        let _ = parser.psci();
+        let _ = DeviceTree::new(&parser);
+        for token in dtb_parser::StructTokenIter::new(&parser) {
+            let Ok(dtb_parser::StructToken::BeginNode { address }) = token else {
+                continue;
+            };
+            let node = DtbNode::new(&parser, address);
+            let _ = node.name();
+            let _ = node.parent();
+            for property in node.properties().flatten() {
+                let _ = property.as_str_list().count();
+                let _ = property.as_cells().map(|cells| cells.count());
+            }
+            if let Ok(reg) = node.reg() {
+                let _ = reg.count();
+            }
+            if let Ok(interrupts) = node.interrupts() {
+                let _ = interrupts.count();
+            }
+            for clock in node.clocks().into_iter().flatten().flatten() {
+                let _ = clock.rate();
+            }
+            for config in node
+                .pinctrl("default")
+                .into_iter()
+                .flatten()
+                .flatten()
+                .flatten()
+            {
+                let _ = (config.pins(), config.function(), config.bias());
+            }
+        }
+        let mut buf = vec![0; 0x4000];
+        let _ = parser.write_modified(&mut buf, &[]);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn malformed_blob_fails_cleanly(
+            patches in proptest::collection::vec((proptest::num::usize::ANY, proptest::num::u8::ANY), 1..8),
+            truncate in proptest::option::of(proptest::num::usize::ANY),
+        ) {
+            let mut blob = std::fs::read("test/rpi5.dtb").expect("failed to load dtb files");
+            for (index, byte) in patches {
+                let len = blob.len();
+                blob[index % len] = byte;
+            }
+            if let Some(len) = truncate {
+                blob.truncate(len % blob.len());
+            }
+            walk_malformed(blob);
+        }
+
+        #[test]
+        fn random_blob_fails_cleanly(body in proptest::collection::vec(proptest::num::u8::ANY, 0..512)) {
+            // a valid magic and plausible sizes so that the parser goes past the header
+            let mut blob = 0xd00d_feed_u32.to_be_bytes().to_vec();
+            blob.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+            blob.extend_from_slice(&body);
+            walk_malformed(blob);
+        }
+    }
+
+    fn load_fixture(path: &str) -> Vec<u8> {
+        std::fs::read(path).expect("failed to load dtb files")
+    }
+
+    #[test]
+    fn vendored_fixtures() {
+        let blob = load_fixture("test/qemu-virt.dtb");
+        let parser = DtbParser::from_bytes(&blob).unwrap();
+        let uart = parser.find_node_by_path("/pl011@9000000").unwrap().unwrap();
+        assert_eq!(
+            uart.reg().unwrap().next().unwrap().unwrap(),
+            (0x900_0000, 0x1000)
+        );
+        assert_eq!(parser.cpus().unwrap().count(), 2);
+        let psci = parser.psci().unwrap().unwrap();
+        assert_eq!(psci.method(), PsciMethod::Hvc);
+        assert_eq!(psci.cpu_on().unwrap(), Some(0xc400_0003));
+        let mut virtio = 0;
+        parser
+            .find_node(None, Some("virtio,mmio"), &mut |_| {
+                virtio += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(virtio, 2);
+
+        let blob = load_fixture("test/empty.dtb");
+        let parser = DtbParser::from_bytes(&blob).unwrap();
+        let root = parser.root().unwrap();
+        assert_eq!(root.properties().count(), 0);
+        assert_eq!(root.children().count(), 0);
+        assert_eq!(parser.memory_reservations().count(), 0);
+
+        // walk has no depth limit, the ancestors of a node are only tracked up to MAX_DEPTH
+        let blob = load_fixture("test/deep.dtb");
+        let parser = DtbParser::from_bytes(&blob).unwrap();
+        let path: String = (1..=34).map(|i| format!("/level{}", i)).collect();
+        let deepest = parser.find_node_by_path(&path).unwrap().unwrap();
+        assert_eq!(
+            deepest.property("depth").unwrap().unwrap().as_u32(),
+            Some(34)
+        );
+        assert_eq!(
+            deepest.parent().err().map(|e| e.kind()),
+            Some(DtbErrorKind::TooDeep)
+        );
+
+        let blob = load_fixture("test/values.dtb");
+        let parser = DtbParser::from_bytes(&blob).unwrap();
+        assert_eq!(
+            parser.memory_reservations().collect::<Vec<_>>(),
+            [
+                (0x1000_0000, 0x4000),
+                (0x2000_0000, 0x10_0000),
+                (0xffff_ffff_0000_0000, 0x1000)
+            ]
+        );
+        let root = parser.root().unwrap();
+        let value = |name| root.property(name).unwrap().unwrap().value();
+        assert!(value("empty").is_empty());
+        assert_eq!(value("odd-bytes"), [1, 2, 3]);
+        assert_eq!(
+            value("bytes-and-cells"),
+            [0xab, 0xcd, 0x12, 0x34, 0x56, 0x78]
+        );
+        assert_eq!(value("u8-cells"), [1, 2, 3]);
+        assert_eq!(value("u16-cells"), [1, 2, 3, 4]);
+        assert_eq!(value("expression"), [0, 0, 0, 16, 0, 0, 0, 17, 0, 0, 0, 20]);
+        assert_eq!(value("char-cell"), [0, 0, 0, b'A']);
+        assert_eq!(
+            root.property("u64-cells").unwrap().unwrap().as_u64(),
+            Some(0x1234_5678_9abc_def0)
+        );
+        assert_eq!(
+            root.property("escaped")
+                .unwrap()
+                .unwrap()
+                .as_str_list()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+            ["tab\there", "quote\"", "back\\slash"]
+        );
+        assert_eq!(value("path"), b"/node@1000\0");
+        assert!(root.property("removed").unwrap().is_none());
+        assert!(parser.find_node_by_path("/gone").unwrap().is_none());
+        let target = parser.find_node_by_path("/node@1000").unwrap().unwrap();
+        assert_eq!(
+            target.property("status").unwrap().unwrap().as_str(),
+            Some("okay")
+        );
+        // phandle 1 is taken by node@2000, so the first free one is given to node@1000
+        assert_eq!(target.phandle().unwrap(), Some(2));
+        assert_eq!(value("phandles"), [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2]);
+        let child = parser
+            .find_node_by_path("/parent-b/child")
+            .unwrap()
+            .unwrap();
+        assert_eq!(child.property("value").unwrap().unwrap().as_u32(), Some(2));
+    }
+
+    // records every token so that two blobs can be compared
+    #[derive(Default, PartialEq, Debug)]
+    struct TokenLog(Vec<String>);
+
+    impl<'a> DtbVisitor<'a> for TokenLog {
+        fn begin_node(&mut self, name: &'a str, depth: usize) -> ControlFlow<()> {
+            self.0.push(format!("{} {} {{", depth, name));
+            ControlFlow::Continue(())
+        }
+
+        fn property(&mut self, name: &'a str, value: &'a [u8]) -> ControlFlow<()> {
+            self.0.push(format!("{} = {:?}", name, value));
+            ControlFlow::Continue(())
+        }
+
+        fn end_node(&mut self, depth: usize) -> ControlFlow<()> {
+            self.0.push(format!("{} }}", depth));
+            ControlFlow::Continue(())
+        }
+    }
+
+    // the tokens are compared first for a readable failure, then the bytes
+    fn assert_same_blob(blob: &[u8], expected_blob: &[u8], name: &str) {
+        let compiled = DtbParser::from_bytes(blob).unwrap();
+        let expected = DtbParser::from_bytes(expected_blob).unwrap();
+
+        assert_eq!(compiled.header(), expected.header(), "{}", name);
+        assert!(
+            compiled
+                .memory_reservations()
+                .eq(expected.memory_reservations())
+        );
+        let (mut compiled_log, mut expected_log) = (TokenLog::default(), TokenLog::default());
+        let _ = compiled.walk(&mut compiled_log).unwrap();
+        let _ = expected.walk(&mut expected_log).unwrap();
+        assert_eq!(compiled_log, expected_log, "{}", name);
+        assert!(blob == expected_blob, "{}", name);
+    }
+
+    // every fixture with the path of its committed blob
+    fn fixtures() -> impl Iterator<Item = (std::path::PathBuf, String)> {
+        std::fs::read_dir("fixtures").unwrap().map(|entry| {
+            let input = entry.unwrap().path();
+            let stem = input.file_stem().unwrap().to_str().unwrap();
+            let blob = if std::fs::exists(format!("test/{}.dtbo", stem)).unwrap() {
+                format!("test/{}.dtbo", stem)
+            } else {
+                format!("test/{}.dtb", stem)
+            };
+            (input, blob)
+        })
+    }
+
+    // the blobs in test/ are written by dtsc for now, so this only keeps dtsc from changing
+    // them. `fixtures_match_dtc` checks them against dtc
+    #[test]
+    fn builtin_compiler_matches_committed_blobs() {
+        for (input, path) in fixtures() {
+            let source = std::fs::read_to_string(&input).unwrap();
+            let blob = dtsc::compile(&source, true).unwrap();
+            assert_same_blob(&blob, &std::fs::read(&path).unwrap(), &path);
+        }
+    }
+
+    // cargo test -p dtb -- --ignored, with dtc in PATH
+    #[test]
+    #[ignore = "needs dtc in PATH"]
+    fn fixtures_match_dtc() {
+        for (input, path) in fixtures() {
+            let output = std::process::Command::new("dtc")
+                .args(["-@", "-I", "dts", "-O", "dtb"])
+                .arg(&input)
+                .output()
+                .expect("dtc is not installed");
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_same_blob(&output.stdout, &std::fs::read(&path).unwrap(), &path);
+        }
+    }
 }
 
 #[cfg(test)]
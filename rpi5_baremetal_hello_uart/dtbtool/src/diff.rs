@@ -0,0 +1,130 @@
+// node by node comparison of two blobs
+
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+
+use dtb::{DtbError, DtbParser, DtbVisitor};
+
+use crate::dts::format_value;
+
+/// compares the trees and returns one line per difference, sorted by the node path
+///
+/// "- " lines only exist in `old` and "+ " lines only in `new`, a removed or added node is
+/// reported once without its properties and children
+pub fn diff(old: &DtbParser<'_>, new: &DtbParser<'_>) -> Result<Vec<String>, DtbError> {
+    let old = NodeMap::collect(old)?;
+    let new = NodeMap::collect(new)?;
+    let mut lines = Vec::new();
+    let mut paths: Vec<&String> = old.nodes.keys().chain(new.nodes.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let (old_props, new_props) = match (old.nodes.get(path), new.nodes.get(path)) {
+            (Some(old_props), Some(new_props)) => (old_props, new_props),
+            (old_props, _) => {
+                // only the topmost node which is missing on one side is reported
+                let parent = &path[..path.rfind('/').unwrap().max(1)];
+                if old.nodes.contains_key(parent) && new.nodes.contains_key(parent) {
+                    let sign = if old_props.is_some() { '-' } else { '+' };
+                    lines.push(format!("{} {}", sign, path));
+                }
+                continue;
+            }
+        };
+        let mut names: Vec<&&str> = old_props.keys().chain(new_props.keys()).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let (old_value, new_value) = (old_props.get(*name), new_props.get(*name));
+            if old_value == new_value {
+                continue;
+            }
+            if let Some(value) = old_value {
+                lines.push(property_line('-', path, name, value));
+            }
+            if let Some(value) = new_value {
+                lines.push(property_line('+', path, name, value));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+fn property_line(sign: char, path: &str, name: &str, value: &[u8]) -> String {
+    if value.is_empty() {
+        format!("{} {}:{};", sign, path, name)
+    } else {
+        format!("{} {}:{} = {};", sign, path, name, format_value(value))
+    }
+}
+
+// properties of every node by its full path
+struct NodeMap<'a> {
+    nodes: BTreeMap<String, BTreeMap<&'a str, &'a [u8]>>,
+    // path of the current node and its ancestors
+    stack: Vec<String>,
+}
+
+impl<'a> NodeMap<'a> {
+    fn collect(parser: &'a DtbParser<'a>) -> Result<Self, DtbError> {
+        let mut map = Self {
+            nodes: BTreeMap::new(),
+            stack: Vec::new(),
+        };
+        let _ = parser.walk(&mut map)?;
+        Ok(map)
+    }
+}
+
+impl<'a> DtbVisitor<'a> for NodeMap<'a> {
+    fn begin_node(&mut self, name: &'a str, _depth: usize) -> ControlFlow<()> {
+        let path = match self.stack.last() {
+            None => String::from("/"),
+            Some(parent) if parent == "/" => format!("/{}", name),
+            Some(parent) => format!("{}/{}", parent, name),
+        };
+        self.nodes.entry(path.clone()).or_default();
+        self.stack.push(path);
+        ControlFlow::Continue(())
+    }
+
+    fn property(&mut self, name: &'a str, value: &'a [u8]) -> ControlFlow<()> {
+        let path = self.stack.last().unwrap();
+        self.nodes.get_mut(path).unwrap().insert(name, value);
+        ControlFlow::Continue(())
+    }
+
+    fn end_node(&mut self, _depth: usize) -> ControlFlow<()> {
+        self.stack.pop();
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtb::DtbEdit;
+
+    #[test]
+    fn diff_edited_blob() {
+        let test_data = std::fs::read("../dtb/test/rpi5.dtb").expect("failed to load dtb files");
+        let old = DtbParser::from_bytes(&test_data).unwrap();
+        assert!(diff(&old, &old).unwrap().is_empty());
+
+        let edits = [
+            DtbEdit::SetProperty {
+                path: "/chosen",
+                name: "bootargs",
+                value: b"console=ttyAMA10\0",
+            },
+            DtbEdit::RemoveNode { path: "/psci" },
+        ];
+        let mut buf = vec![0u8; test_data.len() + 0x1000];
+        let new = DtbParser::from_bytes(old.write_modified(&mut buf, &edits).unwrap()).unwrap();
+        let lines = diff(&old, &new).unwrap();
+        assert!(lines.contains(&String::from("- /psci")));
+        assert!(lines.contains(&String::from("+ /chosen:bootargs = \"console=ttyAMA10\";")));
+        // the children and properties of the deleted node are not listed
+        assert!(!lines.iter().any(|l| l.starts_with("- /psci:")));
+    }
+}
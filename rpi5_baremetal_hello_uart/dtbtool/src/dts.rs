@@ -0,0 +1,168 @@
+// prints a blob in the dts syntax of `dtc -I dtb -O dts`
+
+use std::fmt::Write;
+use std::ops::ControlFlow;
+
+use dtb::{DtbError, DtbParser, DtbVisitor};
+
+pub fn to_dts(parser: &DtbParser<'_>) -> Result<String, DtbError> {
+    let mut printer = DtsPrinter {
+        out: String::from("/dts-v1/;\n\n"),
+        depth: 0,
+        after_property: false,
+    };
+    for (address, size) in parser.memory_reservations() {
+        writeln!(
+            printer.out,
+            "/memreserve/ {:#018x} {:#018x};",
+            address, size
+        )
+        .unwrap();
+    }
+    let _ = parser.walk(&mut printer)?;
+    Ok(printer.out)
+}
+
+/// formats the value of a property after "name = ", guessing its type like dtc does
+///
+/// string lists are tried first, then cells, and everything else is a byte string
+pub fn format_value(value: &[u8]) -> String {
+    if let Some(strings) = as_strings(value) {
+        let strings: Vec<String> = strings
+            .iter()
+            .map(|s| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        return strings.join(", ");
+    }
+    if value.len().is_multiple_of(4) {
+        let cells: Vec<String> = value
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|c| format!("{:#04x}", u32::from_be_bytes(*c)))
+            .collect();
+        return format!("<{}>", cells.join(" "));
+    }
+    let bytes: Vec<String> = value.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("[{}]", bytes.join(" "))
+}
+
+// None unless every string is non-empty and printable
+fn as_strings(value: &[u8]) -> Option<Vec<&str>> {
+    let value = value.strip_suffix(&[0])?;
+    value
+        .split(|&b| b == 0)
+        .map(|s| {
+            (!s.is_empty() && s.iter().all(|b| (0x20..0x7f).contains(b)))
+                .then(|| std::str::from_utf8(s).unwrap())
+        })
+        .collect()
+}
+
+struct DtsPrinter {
+    out: String,
+    // depth of the properties of the current node
+    depth: usize,
+    // dtc separates the properties and each child node with an empty line
+    after_property: bool,
+}
+
+impl DtsPrinter {
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.out.push('\t');
+        }
+    }
+}
+
+impl<'a> DtbVisitor<'a> for DtsPrinter {
+    fn begin_node(&mut self, name: &'a str, depth: usize) -> ControlFlow<()> {
+        if self.after_property {
+            self.out.push('\n');
+        }
+        self.after_property = false;
+        self.indent(depth);
+        let name = if depth == 0 { "/" } else { name };
+        writeln!(self.out, "{} {{", name).unwrap();
+        self.depth = depth + 1;
+        ControlFlow::Continue(())
+    }
+
+    fn property(&mut self, name: &'a str, value: &'a [u8]) -> ControlFlow<()> {
+        self.indent(self.depth);
+        if value.is_empty() {
+            writeln!(self.out, "{};", name).unwrap();
+        } else {
+            writeln!(self.out, "{} = {};", name, format_value(value)).unwrap();
+        }
+        self.after_property = true;
+        ControlFlow::Continue(())
+    }
+
+    fn end_node(&mut self, depth: usize) -> ControlFlow<()> {
+        self.indent(depth);
+        self.out.push_str("};\n");
+        self.depth = depth;
+        self.after_property = true;
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn compile(dts: &str) -> Vec<u8> {
+        let dir = std::env::temp_dir().join(format!("dtbtool-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (dts_path, dtb_path) = (dir.join("printed.dts"), dir.join("printed.dtb"));
+        std::fs::write(&dts_path, dts).unwrap();
+        let status = Command::new("dtc")
+            .args(["-I", "dts", "-O", "dtb", "-o"])
+            .arg(&dtb_path)
+            .arg(&dts_path)
+            .stdin(Stdio::null())
+            .status()
+            .expect("dtc is not installed");
+        assert!(status.success(), "dtc rejected:\n{}", dts);
+        let blob = std::fs::read(&dtb_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        blob
+    }
+
+    // the printed tree compiled again has to be the same as the original on every node
+    //
+    // cargo test -p dtbtool -- --ignored, with dtc in PATH
+    #[test]
+    #[ignore = "needs dtc in PATH"]
+    fn dtc_round_trip() {
+        let test_data = std::fs::read("../dtb/test/rpi5.dtb").expect("failed to load dtb files");
+        let original = DtbParser::from_bytes(&test_data).unwrap();
+        let dts = to_dts(&original).unwrap();
+        assert!(dts.starts_with("/dts-v1/;\n"));
+        assert!(dts.contains("\tcompatible = \"raspberrypi,5-model-b\", \"brcm,bcm2712\";\n"));
+
+        let blob = compile(&dts);
+        let compiled = DtbParser::from_bytes(&blob).unwrap();
+
+        assert_eq!(
+            crate::diff::diff(&original, &compiled).unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(to_dts(&compiled).unwrap(), dts);
+    }
+
+    #[test]
+    fn guess_value_type() {
+        assert_eq!(format_value(b"okay\0"), "\"okay\"");
+        assert_eq!(format_value(b"a\"b\0c\0"), "\"a\\\"b\", \"c\"");
+        assert_eq!(
+            format_value(&[0, 0, 0, 1, 0x7d, 0, 0x10, 0]),
+            "<0x01 0x7d001000>"
+        );
+        assert_eq!(format_value(&[1, 2, 0xab]), "[01 02 ab]");
+        // an empty string in the middle is not a string list
+        assert_eq!(format_value(b"ab\0\0cd\0\0"), "<0x61620000 0x63640000>");
+    }
+}
@@ -0,0 +1,57 @@
+// host tool to inspect dtb files with the parser of the dtb crate
+//
+// usage:
+//   dtbtool print <file.dtb>
+//   dtbtool diff <old.dtb> <new.dtb>
+
+mod diff;
+mod dts;
+
+use std::process::ExitCode;
+
+use dtb::DtbParser;
+
+const USAGE: &str = "Usage: dtbtool [print <file.dtb> | diff <old.dtb> <new.dtb>]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["print", file] => read(file).and_then(|blob| {
+            let parser = parse(file, &blob)?;
+            print!(
+                "{}",
+                dts::to_dts(&parser).map_err(|e| format!("{}: {}", file, e))?
+            );
+            Ok(ExitCode::SUCCESS)
+        }),
+        ["diff", old, new] => read(old).and_then(|old_blob| {
+            let new_blob = read(new)?;
+            let (old_parser, new_parser) = (parse(old, &old_blob)?, parse(new, &new_blob)?);
+            let lines = diff::diff(&old_parser, &new_parser)
+                .map_err(|e| format!("{} or {}: {}", old, new, e))?;
+            for line in &lines {
+                println!("{}", line);
+            }
+            // same convention as diff(1)
+            Ok(if lines.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(1)
+            })
+        }),
+        _ => Err(String::from(USAGE)),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        ExitCode::from(2)
+    })
+}
+
+fn read(file: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(file).map_err(|e| format!("failed to read {}: {}", file, e))
+}
+
+fn parse<'a>(file: &str, blob: &'a [u8]) -> Result<DtbParser<'a>, String> {
+    DtbParser::from_bytes(blob).map_err(|e| format!("{}: {}", file, e))
+}
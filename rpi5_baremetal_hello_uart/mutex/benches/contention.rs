@@ -0,0 +1,50 @@
+// throughput of the spin locks when every core contends for one counter
+//
+// cargo bench -p mutex
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mutex::{McsLock, SpinLock, TicketLock};
+
+const ITERATIONS: usize = 100_000;
+
+// the four Cortex-A76 of the Raspberry Pi 5
+const THREADS: usize = 4;
+
+fn bench<L: Send + Sync + 'static>(name: &str, lock: L, increment: fn(&L)) {
+    let lock = Arc::new(lock);
+    let start = Instant::now();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || {
+                for _ in 0..ITERATIONS {
+                    increment(black_box(&lock));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    report(name, start.elapsed());
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let ops = (THREADS * ITERATIONS) as f64 / elapsed.as_secs_f64();
+    println!("{:<12} {:>10.2?} {:>12.0} locks/s", name, elapsed, ops);
+}
+
+fn main() {
+    println!("{} threads x {} locks", THREADS, ITERATIONS);
+    bench("SpinLock", SpinLock::new(0usize), |lock| *lock.lock() += 1);
+    bench("TicketLock", TicketLock::new(0usize), |lock| {
+        *lock.lock() += 1
+    });
+    bench("McsLock", McsLock::<usize, THREADS>::new(0), |lock| {
+        *lock.lock() += 1
+    });
+}
@@ -0,0 +1,158 @@
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use crate::{SpinLock, SpinLockGuard};
+
+/// SpinLock for data which is also used by interrupt handlers
+///
+/// the guard masks IRQ and FIQ while it is held, so a handler cannot spin on the lock which the
+/// code it interrupted holds. DAIF is restored on drop, so the locks can be nested
+pub struct IrqSpinLock<T> {
+    lock: SpinLock<T>,
+}
+
+pub struct IrqSpinLockGuard<'a, T> {
+    guard: ManuallyDrop<SpinLockGuard<'a, T>>,
+    daif: u64,
+    // DAIF belongs to the core which saved it, so the guard must be dropped there
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T> IrqSpinLock<T> {
+    crate::sync::const_fn! {
+        pub fn new(data: T) -> Self {
+            Self {
+                lock: SpinLock::new(data),
+            }
+        }
+    }
+
+    pub fn lock(&'_ self) -> IrqSpinLockGuard<'_, T> {
+        // masked before spinning, otherwise a handler could take the lock after it is acquired
+        let daif = daif::save_and_mask();
+        IrqSpinLockGuard {
+            guard: ManuallyDrop::new(self.lock.lock()),
+            daif,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.lock.into_inner()
+    }
+}
+
+impl<T> Drop for IrqSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // released before the interrupts are unmasked
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        daif::restore(self.daif);
+    }
+}
+
+impl<T> Deref for IrqSpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+mod daif {
+    use core::arch::asm;
+
+    // the asm is a compiler barrier (no nomem), so nothing of the critical section moves out
+    pub(crate) fn save_and_mask() -> u64 {
+        let daif: u64;
+        unsafe {
+            asm!(
+                "mrs {}, daif",
+                // I and F
+                "msr daifset, #0b0011",
+                out(reg) daif,
+                options(nostack, preserves_flags)
+            );
+        }
+        daif
+    }
+
+    pub(crate) fn restore(daif: u64) {
+        unsafe { asm!("msr daif, {}", in(reg) daif, options(nostack, preserves_flags)) };
+    }
+}
+
+// the host has no interrupts to mask, the tests check the nesting with a simulated DAIF
+#[cfg(not(all(target_arch = "aarch64", target_os = "none")))]
+mod daif {
+    #[cfg(test)]
+    std::thread_local! {
+        pub(crate) static DAIF: core::cell::Cell<u64> = const { core::cell::Cell::new(0) };
+    }
+    #[cfg(test)]
+    pub(crate) const IRQ_FIQ: u64 = 0b0011 << 6;
+
+    pub(crate) fn save_and_mask() -> u64 {
+        #[cfg(test)]
+        return DAIF.with(|daif| daif.replace(daif.get() | IRQ_FIQ));
+        #[cfg(not(test))]
+        0
+    }
+
+    pub(crate) fn restore(daif: u64) {
+        #[cfg(test)]
+        DAIF.with(|cell| cell.set(daif));
+        #[cfg(not(test))]
+        let _ = daif;
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn masked() -> bool {
+        daif::DAIF.with(|daif| daif.get() & daif::IRQ_FIQ != 0)
+    }
+
+    #[test]
+    fn irq_spin_lock_restores_daif() {
+        let (outer, inner) = (IrqSpinLock::new(1), IrqSpinLock::new(2));
+        assert!(!masked());
+        let a = outer.lock();
+        assert!(masked());
+        {
+            let b = inner.lock();
+            assert_eq!(*a + *b, 3);
+        }
+        // still masked by the outer guard
+        assert!(masked());
+        drop(a);
+        assert!(!masked());
+
+        let counter = Arc::new(IrqSpinLock::new(0));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                    assert!(!masked());
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*counter.lock(), 8 * 1000);
+    }
+}
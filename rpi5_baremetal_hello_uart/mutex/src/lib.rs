@@ -1,8 +1,18 @@
 #![cfg_attr(not(test), no_std)]
 
-use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use sync::{AtomicBool, AtomicUsize, Ordering, UnsafeCell, const_fn};
+
+mod irq;
+mod mcs;
+mod once;
+mod sync;
+mod ticket;
+pub use irq::{IrqSpinLock, IrqSpinLockGuard};
+pub use mcs::{McsLock, McsLockGuard};
+pub use once::{Lazy, Once, OnceLock};
+pub use ticket::{TicketLock, TicketLockGuard};
 
 // 基本的に単コアのみで動作を前提としている
 
@@ -18,24 +28,30 @@ pub struct SpinLockGuard<'a, T> {
 unsafe impl<T: Send> Sync for SpinLock<T> {}
 
 impl<T> SpinLock<T> {
-    pub const fn new(data: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            data: UnsafeCell::new(data),
+    const_fn! {
+        pub fn new(data: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                data: UnsafeCell::new(data),
+            }
         }
     }
 
-    // TODO critical section (no interrupt?)
+    // interrupts are not masked, data shared with interrupt handlers uses IrqSpinLock
     pub fn lock(&'_ self) -> SpinLockGuard<'_, T> {
         while self
             .locked
             .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
         {
-            core::hint::spin_loop();
+            sync::spin_loop();
         }
         SpinLockGuard { lock: self }
     }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
 }
 
 impl<T> Drop for SpinLockGuard<'_, T> {
@@ -47,23 +63,30 @@ impl<T> Drop for SpinLockGuard<'_, T> {
 impl<T> Deref for SpinLockGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.lock.data.get() }
+        self.lock.data.with(|data| unsafe { &*data })
     }
 }
 
 impl<T> DerefMut for SpinLockGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.lock.data.get() }
+        self.lock.data.with_mut(|data| unsafe { &mut *data })
     }
 }
 
+/// reader-writer spin lock which prefers writers
+///
+/// - a writer which is waiting blocks new readers, so a stream of readers cannot starve it.
+///   the readers which already hold the lock finish first
+/// - one upgradable reader can hold the lock with the readers. it excludes writers and the
+///   other upgradable readers, so it can become a writer without releasing the lock
+/// - a writer can downgrade to a reader without letting another writer in
+///
+/// so a core which already holds the lock must not wait for it again: a writer waiting in
+/// between blocks the second guard forever. `try_read` and the other try methods can be used
 pub struct RWLock<T> {
-    /// 最上位ビットをwrite flagとして利用する
-    /// その他のビットはread countとして利用
-    ///
-    /// readではwrite flagとwrite待ちflagがなければread countを増やして読む
-    /// writeではすべてのbitが立っていなければ書き込み、write flagを立てて待つ
-    read_count_write_lock_flag: AtomicUsize,
+    /// WRITER, UPGRADABLE and WRITER_WAITING in the low bits and the count of the readers
+    /// above them in units of READER
+    state: AtomicUsize,
     data: UnsafeCell<T>,
 }
 
@@ -75,76 +98,187 @@ pub struct RWLockWriteGuard<'a, T> {
     lock: &'a RWLock<T>,
 }
 
+pub struct RWLockUpgradableGuard<'a, T> {
+    lock: &'a RWLock<T>,
+}
+
 unsafe impl<T: Send + Sync> Sync for RWLock<T> {}
 unsafe impl<T: Send> Send for RWLock<T> {}
 
 impl<T> RWLock<T> {
-    const WRITE_FLAG: usize = 1 << (usize::BITS - 1);
-    pub const fn new(data: T) -> Self {
-        Self {
-            read_count_write_lock_flag: AtomicUsize::new(0),
-            data: UnsafeCell::new(data),
+    const WRITER: usize = 1;
+    const UPGRADABLE: usize = 1 << 1;
+    const WRITER_WAITING: usize = 1 << 2;
+    const READER: usize = 1 << 3;
+
+    const_fn! {
+        pub fn new(data: T) -> Self {
+            Self {
+                state: AtomicUsize::new(0),
+                data: UnsafeCell::new(data),
+            }
         }
     }
 
     pub fn read(&'_ self) -> RWLockReadGuard<'_, T> {
         loop {
-            let n = self.read_count_write_lock_flag.load(Ordering::Relaxed);
-            // 書き込み、書き込み待ちフラグが立っていないかを検証し、立っていればspin
-            if n & Self::WRITE_FLAG == 0
-                && self
-                    .read_count_write_lock_flag
-                    .compare_exchange_weak(n, n + 1, Ordering::Acquire, Ordering::Relaxed)
-                    .is_ok()
-            {
-                return RWLockReadGuard { lock: self };
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            sync::spin_loop();
+        }
+    }
+
+    /// None while a writer holds or waits for the lock
+    pub fn try_read(&'_ self) -> Option<RWLockReadGuard<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        // retried only while other readers change the count
+        while state & (Self::WRITER | Self::WRITER_WAITING) == 0 {
+            match self.state.compare_exchange_weak(
+                state,
+                state + Self::READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(RWLockReadGuard { lock: self }),
+                Err(current) => state = current,
             }
-            core::hint::spin_loop();
         }
+        None
     }
 
     pub fn write(&'_ self) -> RWLockWriteGuard<'_, T> {
         loop {
-            let n = self.read_count_write_lock_flag.load(Ordering::Relaxed);
-            // 書き込みや読み込みがなされてないかを検証する
-            if n & Self::WRITE_FLAG != 0 {
-                core::hint::spin_loop;
-                continue;
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            // blocks new readers until a writer takes the lock
+            let state = self.state.load(Ordering::Relaxed);
+            if state & Self::WRITER_WAITING == 0 {
+                self.state.fetch_or(Self::WRITER_WAITING, Ordering::Relaxed);
+            }
+            sync::spin_loop();
+        }
+    }
+
+    /// None while the lock is held in any mode
+    pub fn try_write(&'_ self) -> Option<RWLockWriteGuard<'_, T>> {
+        let state = self.state.load(Ordering::Relaxed);
+        // taking the lock clears WRITER_WAITING, another waiting writer sets it again
+        if state & !Self::WRITER_WAITING == 0
+            && self
+                .state
+                .compare_exchange(state, Self::WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            return Some(RWLockWriteGuard { lock: self });
+        }
+        None
+    }
+
+    /// reads with the right to upgrade to a writer later, see `RWLockUpgradableGuard::upgrade`
+    pub fn upgradable_read(&'_ self) -> RWLockUpgradableGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_upgradable_read() {
+                return guard;
             }
-            match self.read_count_write_lock_flag.compare_exchange_weak(
-                n,
-                n | Self::WRITE_FLAG,
+            sync::spin_loop();
+        }
+    }
+
+    /// None while a writer or another upgradable reader holds the lock or a writer waits
+    pub fn try_upgradable_read(&'_ self) -> Option<RWLockUpgradableGuard<'_, T>> {
+        let blocked = Self::WRITER | Self::UPGRADABLE | Self::WRITER_WAITING;
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & blocked == 0 {
+            match self.state.compare_exchange_weak(
+                state,
+                state | Self::UPGRADABLE,
                 Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
-                Ok(x) => {
-                    if x & !Self::WRITE_FLAG == 0 {
-                        return RWLockWriteGuard { lock: self };
-                    }
-                    while self.read_count_write_lock_flag.load(Ordering::Relaxed)
-                        & !Self::WRITE_FLAG
-                        != 0
-                    {
-                        core::hint::spin_loop();
-                    }
-                    return RWLockWriteGuard { lock: self };
-                }
-                Err(x) => {
-                    // 失敗したらspin
-                    core::hint::spin_loop();
-                    continue;
-                }
+                Ok(_) => return Some(RWLockUpgradableGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> RWLockUpgradableGuard<'a, T> {
+    /// waits for the readers to finish and becomes the writer
+    ///
+    /// no writer can take the lock in between, so what has been read is still valid
+    pub fn upgrade(self) -> RWLockWriteGuard<'a, T> {
+        let mut guard = self;
+        loop {
+            match guard.try_upgrade() {
+                Ok(write) => return write,
+                Err(upgradable) => guard = upgradable,
             }
+            // blocks new readers like a waiting writer
+            let state = guard.lock.state.load(Ordering::Relaxed);
+            if state & RWLock::<T>::WRITER_WAITING == 0 {
+                guard
+                    .lock
+                    .state
+                    .fetch_or(RWLock::<T>::WRITER_WAITING, Ordering::Relaxed);
+            }
+            sync::spin_loop();
+        }
+    }
+
+    /// becomes the writer when no reader holds the lock, gives the guard back otherwise
+    pub fn try_upgrade(self) -> Result<RWLockWriteGuard<'a, T>, Self> {
+        let lock = self.lock;
+        let state = lock.state.load(Ordering::Relaxed);
+        if state & !RWLock::<T>::WRITER_WAITING == RWLock::<T>::UPGRADABLE
+            && lock
+                .state
+                .compare_exchange(
+                    state,
+                    RWLock::<T>::WRITER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            core::mem::forget(self);
+            return Ok(RWLockWriteGuard { lock });
         }
-        unreachable!();
+        Err(self)
+    }
+
+    /// becomes a plain reader so that another upgradable reader can take the lock
+    pub fn downgrade(self) -> RWLockReadGuard<'a, T> {
+        let lock = self.lock;
+        core::mem::forget(self);
+        // UPGRADABLE is set, so this is + READER - UPGRADABLE in one step
+        lock.state.fetch_add(
+            RWLock::<T>::READER - RWLock::<T>::UPGRADABLE,
+            Ordering::Release,
+        );
+        RWLockReadGuard { lock }
+    }
+}
+
+impl<'a, T> RWLockWriteGuard<'a, T> {
+    /// becomes a reader without releasing the lock, the waiting readers can then go on
+    pub fn downgrade(self) -> RWLockReadGuard<'a, T> {
+        let lock = self.lock;
+        core::mem::forget(self);
+        // WRITER is set, so this is + READER - WRITER in one step
+        lock.state
+            .fetch_add(RWLock::<T>::READER - RWLock::<T>::WRITER, Ordering::Release);
+        RWLockReadGuard { lock }
     }
 }
 
 impl<T> Drop for RWLockReadGuard<'_, T> {
     fn drop(&mut self) {
         self.lock
-            .read_count_write_lock_flag
-            .fetch_sub(1, Ordering::Release);
+            .state
+            .fetch_sub(RWLock::<T>::READER, Ordering::Release);
     }
 }
 
@@ -152,15 +286,32 @@ impl<T> Deref for RWLockReadGuard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.lock.data.get() }
+        self.lock.data.with(|data| unsafe { &*data })
+    }
+}
+
+impl<T> Drop for RWLockUpgradableGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock
+            .state
+            .fetch_and(!RWLock::<T>::UPGRADABLE, Ordering::Release);
+    }
+}
+
+impl<T> Deref for RWLockUpgradableGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.lock.data.with(|data| unsafe { &*data })
     }
 }
 
 impl<T> Drop for RWLockWriteGuard<'_, T> {
     fn drop(&mut self) {
+        // WRITER_WAITING of the next writer stays
         self.lock
-            .read_count_write_lock_flag
-            .fetch_and(!RWLock::<T>::WRITE_FLAG, Ordering::Release);
+            .state
+            .fetch_and(!RWLock::<T>::WRITER, Ordering::Release);
     }
 }
 
@@ -168,17 +319,17 @@ impl<T> Deref for RWLockWriteGuard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.lock.data.get() }
+        self.lock.data.with(|data| unsafe { &*data })
     }
 }
 
 impl<T> DerefMut for RWLockWriteGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.lock.data.get() }
+        self.lock.data.with_mut(|data| unsafe { &mut *data })
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 mod tests {
     use core_affinity::CoreId;
 
@@ -228,7 +379,8 @@ mod tests {
                 let test_data_clone = Arc::clone(&test_data);
                 thread::spawn(move || {
                     if core_affinity::set_for_current(core_id) {
-                        assert_eq!(0, *test_data_clone.read().lock.read().deref());
+                        // one guard only, a second read waits forever behind a waiting writer
+                        assert_eq!(0, *test_data_clone.read().deref());
                     }
                 })
             })
@@ -257,4 +409,77 @@ mod tests {
         }
         assert_eq!(*test_data.read().deref(), 100 * 1000);
     }
+
+    #[test]
+    fn rw_lock_prefers_writers() {
+        let lock = Arc::new(RWLock::new(0));
+        let reader = lock.read();
+        let writer = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || *lock.write() += 1)
+        };
+        while lock.state.load(Ordering::Relaxed) & RWLock::<usize>::WRITER_WAITING == 0 {
+            thread::yield_now();
+        }
+        // the waiting writer blocks new readers but not the one holding the lock
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_upgradable_read().is_none());
+        assert_eq!(*reader, 0);
+        drop(reader);
+        writer.join().unwrap();
+        assert_eq!(*lock.read(), 1);
+        assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn rw_lock_upgrade_and_downgrade() {
+        let lock = RWLock::new(0);
+        let upgradable = lock.upgradable_read();
+        // readers can join, writers and other upgradable readers cannot
+        let reader = lock.try_read().unwrap();
+        assert!(lock.try_write().is_none());
+        assert!(lock.try_upgradable_read().is_none());
+        let Err(upgradable) = upgradable.try_upgrade() else {
+            panic!("upgraded while a reader holds the lock");
+        };
+        drop(reader);
+
+        let mut writer = upgradable.try_upgrade().ok().unwrap();
+        *writer += 1;
+        assert!(lock.try_read().is_none());
+        let reader = writer.downgrade();
+        assert_eq!(*reader, 1);
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(reader);
+
+        let reader = lock.upgradable_read().downgrade();
+        assert!(lock.try_upgradable_read().is_some());
+        drop(reader);
+        *lock.upgradable_read().upgrade() += 1;
+        assert_eq!(*lock.try_write().unwrap(), 2);
+        assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn rw_lock_upgrade_waits_for_readers() {
+        let lock = Arc::new(RWLock::new(0));
+        let upgradable = lock.upgradable_read();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        // the upgrade happens once, so a reader sees 0 or 1 only
+                        assert!(*lock.read() <= 1);
+                    }
+                })
+            })
+            .collect();
+        *upgradable.upgrade() += 1;
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(*lock.read(), 1);
+    }
 }
@@ -0,0 +1,196 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::sync::{self, AtomicBool, AtomicUsize, Ordering, UnsafeCell, const_fn};
+
+/// queued spin lock of Mellor-Crummey and Scott, FIFO like TicketLock
+///
+/// each waiter spins on its own node and the holder hands the lock to the next one, so an
+/// unlock touches only the cache line of the next waiter. the nodes live in the lock instead of
+/// on the stack of the waiters so that the guard can be moved like the other guards. up to `N`
+/// cores queue at once, a further core spins until a node is free (without the FIFO order)
+pub struct McsLock<T, const N: usize = 4> {
+    // index + 1 of the last node of the queue, 0 when the lock is free
+    tail: AtomicUsize,
+    nodes: [McsNode; N],
+    data: UnsafeCell<T>,
+}
+
+struct McsNode {
+    in_use: AtomicBool,
+    waiting: AtomicBool,
+    // index + 1 of the next node, 0 until the successor links itself
+    next: AtomicUsize,
+}
+
+impl McsNode {
+    const_fn! {
+        fn new() -> Self {
+            Self {
+                in_use: AtomicBool::new(false),
+                waiting: AtomicBool::new(false),
+                next: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[cfg(not(loom))]
+    const fn array<const N: usize>() -> [Self; N] {
+        [const { Self::new() }; N]
+    }
+
+    // the atomics of loom are not const, so the array is built at run time
+    #[cfg(loom)]
+    fn array<const N: usize>() -> [Self; N] {
+        core::array::from_fn(|_| Self::new())
+    }
+}
+
+pub struct McsLockGuard<'a, T, const N: usize = 4> {
+    lock: &'a McsLock<T, N>,
+    node: usize,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for McsLock<T, N> {}
+
+impl<T, const N: usize> McsLock<T, N> {
+    const_fn! {
+        pub fn new(data: T) -> Self {
+            Self {
+                tail: AtomicUsize::new(0),
+                nodes: McsNode::array(),
+                data: UnsafeCell::new(data),
+            }
+        }
+    }
+
+    pub fn lock(&'_ self) -> McsLockGuard<'_, T, N> {
+        let index = self.claim_node();
+        let node = &self.nodes[index];
+        node.next.store(0, Ordering::Relaxed);
+        node.waiting.store(true, Ordering::Relaxed);
+        // AcqRel: the predecessor sees the initialized node, and the lock is taken when the
+        // queue was empty
+        let prev = self.tail.swap(index + 1, Ordering::AcqRel);
+        if prev != 0 {
+            self.nodes[prev - 1]
+                .next
+                .store(index + 1, Ordering::Release);
+            while node.waiting.load(Ordering::Acquire) {
+                sync::spin_loop();
+            }
+        }
+        McsLockGuard {
+            lock: self,
+            node: index,
+        }
+    }
+
+    fn claim_node(&self) -> usize {
+        loop {
+            for (i, node) in self.nodes.iter().enumerate() {
+                if node
+                    .in_use
+                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return i;
+                }
+            }
+            sync::spin_loop();
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T, const N: usize> Drop for McsLockGuard<'_, T, N> {
+    fn drop(&mut self) {
+        let nodes = &self.lock.nodes;
+        let node = &nodes[self.node];
+        let mut next = node.next.load(Ordering::Acquire);
+        if next == 0 {
+            // no successor, the queue becomes empty
+            if self
+                .lock
+                .tail
+                .compare_exchange(self.node + 1, 0, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                node.in_use.store(false, Ordering::Release);
+                return;
+            }
+            // a successor has swapped the tail but not linked itself yet
+            loop {
+                next = node.next.load(Ordering::Acquire);
+                if next != 0 {
+                    break;
+                }
+                sync::spin_loop();
+            }
+        }
+        nodes[next - 1].waiting.store(false, Ordering::Release);
+        node.in_use.store(false, Ordering::Release);
+    }
+}
+
+impl<T, const N: usize> Deref for McsLockGuard<'_, T, N> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.lock.data.with(|data| unsafe { &*data })
+    }
+}
+
+impl<T, const N: usize> DerefMut for McsLockGuard<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.lock.data.with_mut(|data| unsafe { &mut *data })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn mcs_lock_is_fifo() {
+        let lock: Arc<McsLock<Vec<usize>, 8>> = Arc::new(McsLock::new(Vec::new()));
+        let holder = lock.lock();
+        let handles: Vec<_> = (0..7)
+            .map(|i| {
+                let lock_clone = Arc::clone(&lock);
+                let tail = lock.tail.load(Ordering::Acquire);
+                let handle = thread::spawn(move || lock_clone.lock().push(i));
+                // wait until the thread is at the tail of the queue, so the queue is 0, 1, 2, ...
+                while lock.tail.load(Ordering::Acquire) == tail {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+        drop(holder);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*lock.lock(), (0..7).collect::<Vec<_>>());
+
+        // more threads than nodes
+        let counter: Arc<McsLock<usize, 2>> = Arc::new(McsLock::new(0));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(Arc::into_inner(counter).unwrap().into_inner(), 8 * 1000);
+    }
+}
@@ -0,0 +1,201 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// runs an initialization exactly once, the other cores spin until it has finished
+///
+/// panics abort on this target, so there is no poisoning
+pub struct Once {
+    state: AtomicU8,
+}
+
+impl Once {
+    const INCOMPLETE: u8 = 0;
+    const RUNNING: u8 = 1;
+    const COMPLETE: u8 = 2;
+
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(Self::INCOMPLETE),
+        }
+    }
+
+    /// runs `f` if no call has run yet, and returns after it has finished on any core
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        match self.state.compare_exchange(
+            Self::INCOMPLETE,
+            Self::RUNNING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                f();
+                self.state.store(Self::COMPLETE, Ordering::Release);
+            }
+            Err(_) => {
+                while !self.is_completed() {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == Self::COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a value which is set once and then shared, e.g. a driver found in the device tree
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+unsafe impl<T: Send> Send for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// None until the value is set
+    pub fn get(&self) -> Option<&T> {
+        self.once
+            .is_completed()
+            .then(|| unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    /// gives `value` back when the lock is already set
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            Some(value) => Err(value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| unsafe {
+            (*self.value.get()).write(f());
+        });
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// a value which is initialized by `F` on the first access
+pub struct Lazy<T, F = fn() -> T> {
+    lock: OnceLock<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+// `init` is taken only inside the Once
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            lock: OnceLock::new(),
+            init: UnsafeCell::new(Some(init)),
+        }
+    }
+
+    /// initializes the value if it has not been yet
+    pub fn force(this: &Self) -> &T {
+        this.lock.get_or_init(|| {
+            let init = unsafe { (*this.init.get()).take() };
+            init.expect("Lazy is initialized only once")()
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        Self::force(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn initialized_once_across_threads() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<usize> = Lazy::new(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            // long enough for the other threads to wait for it
+            thread::sleep(std::time::Duration::from_millis(10));
+            42
+        });
+        let lock = Arc::new(OnceLock::new());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    assert_eq!(*VALUE, 42);
+                    // exactly one set wins and everyone sees its value
+                    let won = lock.set(i).is_ok();
+                    assert!(lock.get().is_some());
+                    won
+                })
+            })
+            .collect();
+        let winners = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|won| *won)
+            .count();
+        assert_eq!(winners, 1);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(*lock.get_or_init(|| 100), *lock.get().unwrap());
+
+        let once = Once::new();
+        let mut runs = 0;
+        once.call_once(|| runs += 1);
+        once.call_once(|| runs += 1);
+        assert_eq!(runs, 1);
+        assert!(once.is_completed());
+
+        // the value is dropped with the lock
+        let value = Arc::new(());
+        let lock = OnceLock::new();
+        assert!(lock.get().is_none());
+        lock.set(Arc::clone(&value)).unwrap();
+        assert_eq!(lock.set(Arc::clone(&value)).map_err(|_| ()), Err(()));
+        drop(lock);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
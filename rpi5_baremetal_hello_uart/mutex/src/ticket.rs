@@ -0,0 +1,96 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::sync::{self, AtomicUsize, Ordering, UnsafeCell, const_fn};
+
+/// fair spin lock, the cores take the lock in the order they asked for it
+///
+/// every core spins on the same counter, so the cache line bounces between all waiters on each
+/// unlock. McsLock avoids that when many cores contend
+pub struct TicketLock<T> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+}
+
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    const_fn! {
+        pub fn new(data: T) -> Self {
+            Self {
+                next_ticket: AtomicUsize::new(0),
+                now_serving: AtomicUsize::new(0),
+                data: UnsafeCell::new(data),
+            }
+        }
+    }
+
+    pub fn lock(&'_ self) -> TicketLockGuard<'_, T> {
+        // wraps around, which is fine as long as fewer than usize::MAX cores wait
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            sync::spin_loop();
+        }
+        TicketLockGuard { lock: self }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // only the holder writes now_serving
+        self.lock.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.lock.data.with(|data| unsafe { &*data })
+    }
+}
+
+impl<T> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.lock.data.with_mut(|data| unsafe { &mut *data })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn ticket_lock_is_fifo() {
+        let lock = Arc::new(TicketLock::new(Vec::new()));
+        let holder = lock.lock();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let lock_clone = Arc::clone(&lock);
+                let handle = thread::spawn(move || lock_clone.lock().push(i));
+                // wait until the thread has taken its ticket, so the queue is 0, 1, 2, ...
+                while lock.next_ticket.load(Ordering::Relaxed) != i + 2 {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+        drop(holder);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            Arc::into_inner(lock).unwrap().into_inner(),
+            (0..8).collect::<Vec<_>>()
+        );
+    }
+}
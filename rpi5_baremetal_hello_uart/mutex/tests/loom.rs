@@ -0,0 +1,144 @@
+// model checked tests of the locks, loom runs every interleaving of the threads and
+// reports an access to the data which is not ordered by the lock
+//
+// two threads each, as the spinning of a third one makes the interleavings explode
+//
+// RUSTFLAGS="--cfg loom" cargo test -p mutex --release --test loom
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use mutex::{McsLock, RWLock, SpinLock, TicketLock};
+
+#[test]
+fn spin_lock_excludes() {
+    loom::model(|| {
+        let lock = Arc::new(SpinLock::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    // a read and a write which race without the lock
+                    let mut data = lock.lock();
+                    *data += 1;
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*lock.lock(), 2);
+    });
+}
+
+#[test]
+fn ticket_lock_excludes() {
+    loom::model(|| {
+        let lock = Arc::new(TicketLock::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || *lock.lock() += 1)
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(Arc::try_unwrap(lock).ok().unwrap().into_inner(), 2);
+    });
+}
+
+#[test]
+fn mcs_lock_hands_over() {
+    loom::model(|| {
+        // one node per thread, the holder hands the lock to the queued one
+        let lock: Arc<McsLock<usize, 2>> = Arc::new(McsLock::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || *lock.lock() += 1)
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(Arc::try_unwrap(lock).ok().unwrap().into_inner(), 2);
+    });
+}
+
+#[test]
+fn rw_lock_excludes_readers_and_writers() {
+    loom::model(|| {
+        // both values change together under the write lock
+        let lock = Arc::new(RWLock::new((0, 0)));
+        let writer = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || {
+                let mut data = lock.write();
+                data.0 += 1;
+                data.1 += 1;
+            })
+        };
+        let reader = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || {
+                let data = lock.read();
+                assert_eq!(data.0, data.1);
+            })
+        };
+        writer.join().unwrap();
+        reader.join().unwrap();
+        assert_eq!(*lock.read(), (1, 1));
+    });
+}
+
+#[test]
+fn rw_lock_upgrade_is_atomic() {
+    loom::model(|| {
+        let lock = Arc::new(RWLock::new(0));
+        let writer = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || *lock.write() += 10)
+        };
+        // no write can come between the read and the upgrade
+        let upgradable = lock.upgradable_read();
+        let read = *upgradable;
+        let mut data = upgradable.upgrade();
+        assert_eq!(*data, read);
+        *data += 1;
+        drop(data);
+        writer.join().unwrap();
+        assert_eq!(*lock.read(), 11);
+    });
+}
+
+#[test]
+fn guards_release_in_any_order() {
+    loom::model(|| {
+        let lock = Arc::new(RWLock::new(0));
+        let writer = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || {
+                // the readers can go on once the writer downgrades
+                let mut data = lock.write();
+                *data += 1;
+                let data = data.downgrade();
+                assert_eq!(*data, 1);
+            })
+        };
+        // a second guard is only tried, it waits forever behind the writer otherwise
+        let first = lock.read();
+        match lock.try_upgradable_read() {
+            Some(second) => {
+                assert_eq!(*first, *second);
+                drop(first);
+                let third = second.downgrade();
+                drop(third);
+            }
+            None => drop(first),
+        }
+        writer.join().unwrap();
+        // every guard has released its part of the state
+        assert!(lock.try_write().is_some());
+    });
+}
@@ -19,6 +19,9 @@ struct Package {
     name: String, // `cargo test -p <name>` で使用するパッケージ名
 }
 
+// ホストでのみ動くワークスペースのメンバー (testは実行する)
+const HOST_TOOLS: [&str; 1] = ["dtbtool"];
+
 fn main() {
     let mut args = std::env::args().skip(1); // 実行ファイル名 (xtask) をスキップ
 
@@ -51,13 +54,15 @@ fn main() {
 
 fn build(args: &[String]) -> Result<String, &'static str> {
     // ワークスペースのメンバーを取得（xtaskは除外済み）
-    let build_crate_names = match get_workspace_members() {
+    let mut build_crate_names = match get_workspace_members() {
         Ok(names) => names,
         Err(e) => {
             eprintln!("Error getting workspace members: {}", e);
             std::process::exit(1);
         }
     };
+    // ホスト用のツールはstdが必要なのでaarch64-unknown-noneではビルドしない
+    build_crate_names.retain(|name| !HOST_TOOLS.contains(&name.as_str()));
 
     if build_crate_names.is_empty() {
         eprintln!("Warning: No workspace members found to build (excluding xtask).");